@@ -0,0 +1,105 @@
+use crate::display_info::DisplayInfo;
+use crate::frame::{Frame, PixelFormat};
+use crate::window_info::WindowInfo;
+use crate::{Result, ScreenshotError};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+/// How long a capture waits for its first frame unless told otherwise.
+pub const DEFAULT_CAPTURE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A resolved capture target.
+#[derive(Clone, Debug)]
+pub enum CaptureSource {
+    Window(WindowInfo),
+    Monitor(DisplayInfo),
+    Primary,
+}
+
+/// Something that can list capture candidates and turn one of them into a [`Frame`].
+pub trait CaptureBackend {
+    fn enumerate_windows(&self) -> Result<Vec<WindowInfo>>;
+    fn enumerate_displays(&self) -> Result<Vec<DisplayInfo>>;
+    /// Captures a single frame, failing with [`ScreenshotError::CaptureTimeout`]
+    /// if none arrives within `timeout`.
+    fn capture(
+        &self,
+        source: &CaptureSource,
+        pixel_format: PixelFormat,
+        timeout: Duration,
+    ) -> Result<Frame>;
+
+    /// Starts capturing `source` for a series of frames. Backends that can keep
+    /// a capture running between frames should; by default every frame is
+    /// captured from scratch.
+    fn start_session<'a>(
+        &'a self,
+        source: &CaptureSource,
+        pixel_format: PixelFormat,
+    ) -> Result<Box<dyn CaptureSession + 'a>> {
+        Ok(Box::new(RepeatedCapture {
+            backend: self,
+            source: source.clone(),
+            pixel_format,
+        }))
+    }
+
+    /// The dots per inch of the monitor showing `source`, if the backend knows it.
+    fn dpi(&self, _source: &CaptureSource) -> Result<Option<u32>> {
+        Ok(None)
+    }
+
+    /// The top level window the user is working in, if the backend knows it.
+    fn foreground_window(&self) -> Result<Option<WindowInfo>> {
+        Ok(None)
+    }
+
+    /// The top level window under the mouse cursor, if the backend knows it.
+    fn window_under_cursor(&self) -> Result<Option<WindowInfo>> {
+        Ok(None)
+    }
+}
+
+/// A running capture of one source, handing out frames as they're needed.
+pub trait CaptureSession {
+    /// The latest frame, failing with [`ScreenshotError::CaptureTimeout`] if
+    /// none arrives within `timeout`.
+    fn next_frame(&mut self, timeout: Duration) -> Result<Frame>;
+}
+
+/// A session for backends that can only capture one frame at a time.
+struct RepeatedCapture<'a, B: ?Sized> {
+    backend: &'a B,
+    source: CaptureSource,
+    pixel_format: PixelFormat,
+}
+
+impl<B: CaptureBackend + ?Sized> CaptureSession for RepeatedCapture<'_, B> {
+    fn next_frame(&mut self, timeout: Duration) -> Result<Frame> {
+        self.backend
+            .capture(&self.source, self.pixel_format, timeout)
+    }
+}
+
+/// Waits up to `timeout` for a frame sent from a capture callback.
+pub fn receive_frame<T>(receiver: &Receiver<T>, timeout: Duration) -> Result<T> {
+    match receiver.recv_timeout(timeout) {
+        Ok(frame) => Ok(frame),
+        Err(RecvTimeoutError::Timeout) => Err(ScreenshotError::CaptureTimeout(timeout)),
+        Err(RecvTimeoutError::Disconnected) => Err(ScreenshotError::platform(
+            "The frame pool closed before a frame arrived!",
+        )),
+    }
+}
+
+/// The backend used when none is specified, if the platform has one.
+pub fn default_backend() -> Option<Box<dyn CaptureBackend>> {
+    #[cfg(windows)]
+    {
+        Some(Box::<crate::capture::WgcBackend>::default())
+    }
+    #[cfg(not(windows))]
+    {
+        None
+    }
+}
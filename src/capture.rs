@@ -1,11 +1,38 @@
+use crate::backend::{receive_frame, CaptureBackend, CaptureSession, CaptureSource};
+use crate::d3d;
+use crate::display_info::{enumerate_displays, monitor_dpi, DisplayInfo};
+use crate::frame::{Frame, PixelFormat};
 use crate::window_info::WindowInfo;
-use windows::core::BOOL;
-use windows::Win32::Foundation::{HWND, LPARAM};
+use crate::Result;
+use std::cell::OnceCell;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use windows::core::{IInspectable, Interface, BOOL};
+use windows::Foundation::TypedEventHandler;
+use windows::Graphics::Capture::{
+    Direct3D11CaptureFrame, Direct3D11CaptureFramePool, GraphicsCaptureItem, GraphicsCaptureSession,
+};
+use windows::Graphics::DirectX::Direct3D11::IDirect3DDevice;
+use windows::Graphics::DirectX::DirectXPixelFormat;
+use windows::Win32::Foundation::{E_INVALIDARG, HWND, LPARAM, POINT};
+use windows::Win32::Graphics::Direct3D11::{
+    ID3D11Device, ID3D11DeviceContext, ID3D11Resource, ID3D11Texture2D, D3D11_CPU_ACCESS_READ,
+    D3D11_MAPPED_SUBRESOURCE, D3D11_MAP_READ, D3D11_TEXTURE2D_DESC, D3D11_USAGE_STAGING,
+};
 use windows::Win32::Graphics::Dwm::{DwmGetWindowAttribute, DWMWA_CLOAKED, DWM_CLOAKED_SHELL};
+use windows::Win32::Graphics::Dxgi::Common::{
+    DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_R16G16B16A16_FLOAT,
+};
+use windows::Win32::Graphics::Gdi::{
+    MonitorFromWindow, HMONITOR, MONITOR_DEFAULTTONEAREST, MONITOR_DEFAULTTOPRIMARY,
+};
 use windows::Win32::System::Console::GetConsoleWindow;
+use windows::Win32::System::WinRT::Graphics::Capture::IGraphicsCaptureItemInterop;
 use windows::Win32::UI::WindowsAndMessaging::{
-    EnumWindows, GetAncestor, GetShellWindow, GetWindowLongW, IsWindowVisible, GA_ROOT,
-    GWL_EXSTYLE, GWL_STYLE, WS_DISABLED, WS_EX_TOOLWINDOW,
+    EnumWindows, GetAncestor, GetCursorPos, GetDesktopWindow, GetForegroundWindow, GetShellWindow,
+    GetWindowLongW, IsWindowVisible, WindowFromPoint, GA_ROOT, GWL_EXSTYLE, GWL_STYLE, WS_DISABLED,
+    WS_EX_TOOLWINDOW,
 };
 
 struct WindowEnumerationState {
@@ -13,7 +40,7 @@ struct WindowEnumerationState {
     console_window: Option<HWND>,
 }
 
-pub fn enumerate_capturable_windows() -> Vec<WindowInfo> {
+pub fn enumerate_capturable_windows() -> Result<Vec<WindowInfo>> {
     unsafe {
         // TODO: This works for Command Prompt but not Terminal
         let console_window = {
@@ -28,9 +55,10 @@ pub fn enumerate_capturable_windows() -> Vec<WindowInfo> {
             windows: Vec::new(),
             console_window,
         }));
-        EnumWindows(Some(enum_window), LPARAM(state as isize)).unwrap();
+        let result = EnumWindows(Some(enum_window), LPARAM(state as isize));
         let state = Box::from_raw(state);
-        state.windows
+        result?;
+        Ok(state.windows)
     }
 }
 
@@ -59,21 +87,22 @@ pub trait CaptureWindowCandidate {
 impl CaptureWindowCandidate for WindowInfo {
     fn is_capturable_window(&self) -> bool {
         unsafe {
+            let handle = self.hwnd();
             if self.title.is_empty()
-                || self.handle == GetShellWindow()
-                || !IsWindowVisible(self.handle).as_bool()
-                || GetAncestor(self.handle, GA_ROOT) != self.handle
+                || handle == GetShellWindow()
+                || !IsWindowVisible(handle).as_bool()
+                || GetAncestor(handle, GA_ROOT) != handle
             {
                 return false;
             }
 
-            let style = GetWindowLongW(self.handle, GWL_STYLE);
+            let style = GetWindowLongW(handle, GWL_STYLE);
             if style & (WS_DISABLED.0 as i32) == 1 {
                 return false;
             }
 
             // No tooltips
-            let ex_style = GetWindowLongW(self.handle, GWL_EXSTYLE);
+            let ex_style = GetWindowLongW(handle, GWL_EXSTYLE);
             if ex_style & (WS_EX_TOOLWINDOW.0 as i32) == 1 {
                 return false;
             }
@@ -84,7 +113,7 @@ impl CaptureWindowCandidate for WindowInfo {
             {
                 let mut cloaked: u32 = 0;
                 if DwmGetWindowAttribute(
-                    self.handle,
+                    handle,
                     DWMWA_CLOAKED,
                     &mut cloaked as *mut _ as *mut _,
                     std::mem::size_of::<u32>() as u32,
@@ -113,3 +142,317 @@ fn is_known_blocked_window(window_info: &WindowInfo) -> bool {
     // XAML Popups
     window_info.matches_title_and_class_name("PopupHost", "Xaml_WindowedPopupClass")
 }
+
+/// The D3D device captures are copied with, shared by every session.
+#[derive(Clone)]
+pub struct WgcDevice {
+    d3d_device: ID3D11Device,
+    d3d_context: ID3D11DeviceContext,
+    device: IDirect3DDevice,
+}
+
+impl WgcDevice {
+    pub fn new() -> Result<Self> {
+        let d3d_device = d3d::create_d3d_device()?;
+        let d3d_context = unsafe { d3d_device.GetImmediateContext()? };
+        let device = d3d::create_direct3d_device(&d3d_device)?;
+        Ok(Self {
+            d3d_device,
+            d3d_context,
+            device,
+        })
+    }
+}
+
+/// Captures through `Windows.Graphics.Capture`.
+#[derive(Default)]
+pub struct WgcBackend {
+    /// Created on the first capture, so listing windows and displays doesn't
+    /// need a device.
+    device: OnceCell<WgcDevice>,
+}
+
+impl WgcBackend {
+    fn device(&self) -> Result<&WgcDevice> {
+        if let Some(device) = self.device.get() {
+            return Ok(device);
+        }
+        let device = WgcDevice::new()?;
+        Ok(self.device.get_or_init(|| device))
+    }
+}
+
+impl CaptureBackend for WgcBackend {
+    fn enumerate_windows(&self) -> Result<Vec<WindowInfo>> {
+        enumerate_capturable_windows()
+    }
+
+    fn enumerate_displays(&self) -> Result<Vec<DisplayInfo>> {
+        enumerate_displays()
+    }
+
+    fn foreground_window(&self) -> Result<Option<WindowInfo>> {
+        let window_handle = unsafe { GetForegroundWindow() };
+        if window_handle.0.is_null() {
+            return Ok(None);
+        }
+        let root = unsafe { GetAncestor(window_handle, GA_ROOT) };
+        Ok(Some(WindowInfo::new(root)))
+    }
+
+    fn window_under_cursor(&self) -> Result<Option<WindowInfo>> {
+        let window_handle = unsafe {
+            let mut point = POINT::default();
+            GetCursorPos(&mut point)?;
+            WindowFromPoint(point)
+        };
+        if window_handle.0.is_null() {
+            return Ok(None);
+        }
+        let root = unsafe { GetAncestor(window_handle, GA_ROOT) };
+        Ok(Some(WindowInfo::new(root)))
+    }
+
+    fn capture(
+        &self,
+        source: &CaptureSource,
+        pixel_format: PixelFormat,
+        timeout: Duration,
+    ) -> Result<Frame> {
+        let item = create_capture_item(source)?;
+        capture_frame(self.device()?, &item, pixel_format, timeout)
+    }
+
+    fn start_session<'a>(
+        &'a self,
+        source: &CaptureSource,
+        pixel_format: PixelFormat,
+    ) -> Result<Box<dyn CaptureSession + 'a>> {
+        let item = create_capture_item(source)?;
+        Ok(Box::new(WgcSession::start(
+            self.device()?,
+            &item,
+            pixel_format,
+        )?))
+    }
+
+    fn dpi(&self, source: &CaptureSource) -> Result<Option<u32>> {
+        let monitor_handle = unsafe {
+            match source {
+                CaptureSource::Window(window) => {
+                    MonitorFromWindow(window.hwnd(), MONITOR_DEFAULTTONEAREST)
+                }
+                CaptureSource::Monitor(display) => return Ok(Some(display.dpi)),
+                CaptureSource::Primary => {
+                    MonitorFromWindow(GetDesktopWindow(), MONITOR_DEFAULTTOPRIMARY)
+                }
+            }
+        };
+        Ok(Some(monitor_dpi(monitor_handle)?))
+    }
+}
+
+fn create_capture_item(source: &CaptureSource) -> Result<GraphicsCaptureItem> {
+    match source {
+        CaptureSource::Window(window) => create_capture_item_for_window(window.hwnd()),
+        CaptureSource::Monitor(display) => create_capture_item_for_monitor(display.hmonitor()),
+        CaptureSource::Primary => create_capture_item_for_primary_monitor(),
+    }
+}
+
+pub fn create_capture_item_for_window(window_handle: HWND) -> Result<GraphicsCaptureItem> {
+    let interop = windows::core::factory::<GraphicsCaptureItem, IGraphicsCaptureItemInterop>()?;
+    Ok(unsafe { interop.CreateForWindow(window_handle)? })
+}
+
+pub fn create_capture_item_for_monitor(monitor_handle: HMONITOR) -> Result<GraphicsCaptureItem> {
+    let interop = windows::core::factory::<GraphicsCaptureItem, IGraphicsCaptureItemInterop>()?;
+    Ok(unsafe { interop.CreateForMonitor(monitor_handle)? })
+}
+
+pub fn create_capture_item_for_primary_monitor() -> Result<GraphicsCaptureItem> {
+    let monitor_handle = unsafe { MonitorFromWindow(GetDesktopWindow(), MONITOR_DEFAULTTOPRIMARY) };
+    create_capture_item_for_monitor(monitor_handle)
+}
+
+pub fn capture_frame(
+    device: &WgcDevice,
+    item: &GraphicsCaptureItem,
+    pixel_format: PixelFormat,
+    timeout: Duration,
+) -> Result<Frame> {
+    WgcSession::start(device, item, pixel_format)?.next_frame(timeout)
+}
+
+/// Closes the capture session and its frame pool however the capture ends, so a
+/// timeout or error doesn't leave the capture running.
+struct SessionGuard {
+    frame_pool: Direct3D11CaptureFramePool,
+    session: GraphicsCaptureSession,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        let _ = self.session.Close();
+        let _ = self.frame_pool.Close();
+    }
+}
+
+/// One frame pool and capture session on a shared D3D device, kept running for
+/// as many frames as are needed.
+pub struct WgcSession {
+    d3d_device: ID3D11Device,
+    d3d_context: ID3D11DeviceContext,
+    /// The newest frame that hasn't been copied yet. Older ones are closed as
+    /// soon as a newer one arrives, so the frame pool never runs out of buffers.
+    latest: Arc<Mutex<Option<Direct3D11CaptureFrame>>>,
+    arrived: Receiver<()>,
+    last: Option<Frame>,
+    _capture: SessionGuard,
+}
+
+impl WgcSession {
+    pub fn start(
+        device: &WgcDevice,
+        item: &GraphicsCaptureItem,
+        pixel_format: PixelFormat,
+    ) -> Result<Self> {
+        let pixel_format = match pixel_format {
+            PixelFormat::Bgra8 => DirectXPixelFormat::B8G8R8A8UIntNormalized,
+            PixelFormat::Rgba16Float => DirectXPixelFormat::R16G16B16A16Float,
+        };
+
+        let frame_pool = Direct3D11CaptureFramePool::CreateFreeThreaded(
+            &device.device,
+            pixel_format,
+            2,
+            item.Size()?,
+        )?;
+        let session = frame_pool.CreateCaptureSession(item)?;
+        let capture = SessionGuard {
+            frame_pool,
+            session,
+        };
+
+        let latest = Arc::new(Mutex::new(None));
+        let (sender, arrived) = channel();
+        capture.frame_pool.FrameArrived(&TypedEventHandler::<
+            Direct3D11CaptureFramePool,
+            IInspectable,
+        >::new({
+            let latest = latest.clone();
+            move |frame_pool, _| {
+                let frame_pool = frame_pool.as_ref().unwrap();
+                let frame = frame_pool.TryGetNextFrame()?;
+                if let Some(older) = latest.lock().unwrap().replace(frame) {
+                    older.Close()?;
+                }
+                // The receiver is gone once the session has been dropped.
+                let _ = sender.send(());
+                Ok(())
+            }
+        }))?;
+        capture.session.StartCapture()?;
+
+        Ok(Self {
+            d3d_device: device.d3d_device.clone(),
+            d3d_context: device.d3d_context.clone(),
+            latest,
+            arrived,
+            last: None,
+            _capture: capture,
+        })
+    }
+}
+
+impl CaptureSession for WgcSession {
+    fn next_frame(&mut self, timeout: Duration) -> Result<Frame> {
+        let frame = loop {
+            if let Some(frame) = self.latest.lock().unwrap().take() {
+                break frame;
+            }
+            // Frames only arrive when something changes, so nothing new means
+            // the last frame is still what's on screen.
+            if let Some(last) = &self.last {
+                return Ok(last.clone());
+            }
+            receive_frame(&self.arrived, timeout)?;
+        };
+        let texture = copy_frame(&frame, &self.d3d_device, &self.d3d_context)?;
+        frame.Close()?;
+        let frame = get_frame_from_texture(&self.d3d_context, &texture)?;
+        self.last = Some(frame.clone());
+        Ok(frame)
+    }
+}
+
+/// Copies a captured frame into a texture the CPU can read.
+fn copy_frame(
+    frame: &Direct3D11CaptureFrame,
+    d3d_device: &ID3D11Device,
+    d3d_context: &ID3D11DeviceContext,
+) -> Result<ID3D11Texture2D> {
+    unsafe {
+        let source_texture: ID3D11Texture2D =
+            d3d::get_d3d_interface_from_object(&frame.Surface()?)?;
+        let mut desc = D3D11_TEXTURE2D_DESC::default();
+        source_texture.GetDesc(&mut desc);
+        desc.BindFlags = 0;
+        desc.MiscFlags = 0;
+        desc.Usage = D3D11_USAGE_STAGING;
+        desc.CPUAccessFlags = D3D11_CPU_ACCESS_READ.0 as u32;
+        let copy_texture = {
+            let mut texture = None;
+            d3d_device.CreateTexture2D(&desc, None, Some(&mut texture))?;
+            texture.unwrap()
+        };
+
+        d3d_context.CopyResource(Some(&copy_texture.cast()?), Some(&source_texture.cast()?));
+
+        Ok(copy_texture)
+    }
+}
+
+fn get_frame_from_texture(
+    d3d_context: &ID3D11DeviceContext,
+    texture: &ID3D11Texture2D,
+) -> Result<Frame> {
+    unsafe {
+        let mut desc = D3D11_TEXTURE2D_DESC::default();
+        texture.GetDesc(&mut desc as *mut _);
+
+        let format = match desc.Format {
+            DXGI_FORMAT_B8G8R8A8_UNORM => PixelFormat::Bgra8,
+            DXGI_FORMAT_R16G16B16A16_FLOAT => PixelFormat::Rgba16Float,
+            _ => {
+                return Err(
+                    windows::core::Error::new(E_INVALIDARG, "Unsupported pixel format!").into(),
+                )
+            }
+        };
+
+        let resource: ID3D11Resource = texture.cast()?;
+        let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+        d3d_context.Map(
+            Some(&resource.clone()),
+            0,
+            D3D11_MAP_READ,
+            0,
+            Some(&mut mapped),
+        )?;
+
+        // Get a slice of bytes
+        let slice: &[u8] = {
+            std::slice::from_raw_parts(
+                mapped.pData as *const _,
+                (desc.Height * mapped.RowPitch) as usize,
+            )
+        };
+
+        let frame = Frame::from_pitched(desc.Width, desc.Height, format, mapped.RowPitch, slice);
+
+        d3d_context.Unmap(Some(&resource), 0);
+
+        Ok(frame)
+    }
+}
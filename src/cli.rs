@@ -1,43 +1,607 @@
-use clap::Parser;
+use crate::list::{ListFormat, ListKind};
+use clap::error::ErrorKind;
+use clap::{ArgGroup, CommandFactory, Parser, Subcommand};
+use screenshot::{
+    CaptureTarget, ChromaSubsampling, Collision, Color, ColorProfile, EncodeOptions, Encoder,
+    ExrCompression, ExrOptions, FilenameTemplate, ImageFormat, JpegOptions, MatchMode, PixelFormat,
+    PngBitDepth, PngFilter, PngOptions, PngTransfer, Rect, Schedule, ScreenshotError,
+    ToneMapOperator, ToneMapOptions, WebPOptions, WindowFilter, WindowSelection,
+};
+use std::ffi::OsString;
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[clap(group(
+    ArgGroup::new("window_query")
+        .multiple(true)
+        .args(["window", "wait_for_window", "pid", "process", "class", "hwnd"])
+        .conflicts_with("target")
+))]
+#[clap(group(
+    ArgGroup::new("title_query")
+        .multiple(true)
+        .args(["window", "wait_for_window"])
+))]
+#[clap(group(
+    ArgGroup::new("target").args([
+        "monitor",
+        "primary",
+        "region",
+        "all_monitors",
+        "foreground",
+        "under_cursor",
+    ])
+))]
+#[clap(group(ArgGroup::new("series").multiple(true).args(["count", "until"])))]
+#[clap(group(ArgGroup::new("collision").args(["overwrite", "no_clobber", "unique"])))]
 pub struct Args {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
     /// Capture a window who's title contains the provided input.
-    #[clap(short, long, conflicts_with = "monitor", conflicts_with = "primary")]
+    #[clap(short, long)]
     window: Option<String>,
 
+    /// Wait for a window who's title contains the provided input to appear, then capture it.
+    #[clap(long, conflicts_with = "window")]
+    wait_for_window: Option<String>,
+
+    /// How long --wait-for-window waits for the window, e.g. 30s or 2m.
+    #[clap(long, default_value = "30s", value_parser = parse_duration)]
+    pub wait_timeout: Duration,
+
+    /// Only capture windows owned by this process id.
+    #[clap(long)]
+    pid: Option<u32>,
+
+    /// Only capture windows owned by this executable, e.g. notepad.exe.
+    #[clap(long)]
+    process: Option<String>,
+
+    /// Only capture windows with this class name.
+    #[clap(long)]
+    class: Option<String>,
+
+    /// Capture the window with this handle, in hex (0x...) or decimal.
+    #[clap(long, value_parser = parse_window_handle)]
+    hwnd: Option<isize>,
+
+    /// How --window or --wait-for-window is compared against window titles: substring, exact,
+    /// glob or regex.
+    #[clap(long = "match", default_value = "substring", requires = "title_query")]
+    pub match_mode: MatchMode,
+
+    /// Compare --window or --wait-for-window against window titles case sensitively.
+    #[clap(long, requires = "title_query")]
+    pub case_sensitive: bool,
+
+    /// Pick one of several matching windows without asking: first, last, largest, foreground
+    /// or index:N.
+    #[clap(long, requires = "window_query")]
+    pub select: Option<WindowSelection>,
+
+    /// Fail with the list of candidates instead of asking when several windows match.
+    /// Implied when stdin isn't a terminal.
+    #[clap(long)]
+    pub non_interactive: bool,
+
     /// The index of the monitor to screenshot.
-    #[clap(short, long, conflicts_with = "window", conflicts_with = "primary")]
+    #[clap(short, long)]
     monitor: Option<usize>,
 
     /// Capture the primary monitor (default if no params are specified).
-    #[clap(short, long, conflicts_with = "window", conflicts_with = "monitor")]
+    #[clap(short, long)]
     primary: bool,
 
-    /// The output file that will contain the screenshot.
+    /// Capture a rectangle of the virtual desktop, given as x,y,width,height.
+    #[clap(short, long, allow_hyphen_values = true)]
+    region: Option<Rect>,
+
+    /// Capture every monitor, stitched together as they are arranged.
+    #[clap(short, long)]
+    all_monitors: bool,
+
+    /// Capture the window in the foreground.
+    #[clap(short, long)]
+    foreground: bool,
+
+    /// Capture the window under the mouse cursor.
+    #[clap(short, long)]
+    under_cursor: bool,
+
+    /// Wait before capturing, e.g. 3s or 500ms, to open a menu or switch to another window.
+    #[clap(long, value_parser = parse_duration)]
+    pub delay: Option<Duration>,
+
+    /// Count down the --delay on stderr.
+    #[clap(long, requires = "delay")]
+    pub countdown: bool,
+
+    /// Take this many screenshots, one every --interval.
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..))]
+    count: Option<u32>,
+
+    /// The time between screenshots taken with --count or --until, e.g. 500ms.
+    #[clap(long, default_value = "1s", value_parser = parse_duration, requires = "series")]
+    interval: Duration,
+
+    /// Take a screenshot every --interval for this long, e.g. 1m.
+    #[clap(long, value_parser = parse_duration)]
+    until: Option<Duration>,
+
+    /// Give up on the capture if no frame arrives within this long, e.g. 10s.
+    #[clap(long, default_value = "10s", value_parser = parse_duration)]
+    pub timeout: Duration,
+
+    /// Crop the capture to x,y,width,height, relative to the captured window or monitor.
+    #[clap(long, conflicts_with = "region")]
+    pub crop: Option<Rect>,
+
+    /// The pixel format to capture in: bgra8 or rgba16f. Defaults to what the output format stores.
+    #[clap(long)]
+    pub capture_format: Option<PixelFormat>,
+
+    /// The image format to write: png, jpg, webp, jxr, exr or hdr. Defaults to the file extension.
+    #[clap(long)]
+    output_format: Option<ImageFormat>,
+
+    /// The encoder used to write the image: wic (Windows only) or native.
+    #[clap(long)]
+    encoder: Option<Encoder>,
+
+    /// The deflate level used by the native PNG encoder, from 0 to 9. Picks the native encoder.
+    #[clap(long, default_value_t = 6, value_parser = clap::value_parser!(u8).range(0..=9))]
+    png_compression: u8,
+
+    /// The row filter used by the native PNG encoder: none, sub, up, average, paeth or adaptive.
+    /// Picks the native encoder.
+    #[clap(long, default_value = "adaptive")]
+    png_filter: PngFilter,
+
+    /// The bits per channel of PNGs: 8, or 16 for HDR from FP16 captures.
+    #[clap(long, default_value = "8")]
+    png_bit_depth: PngBitDepth,
+
+    /// How 16-bit PNGs store HDR values: pq (BT.2100) or linear (scRGB, where 65535 is 125.0,
+    /// i.e. 10,000 nits, and SDR white is 524).
+    #[clap(long, default_value = "pq")]
+    png_transfer: PngTransfer,
+
+    /// How PNGs signal their color space: none, srgb or icc. 16-bit PNGs get cICP unless none.
+    #[clap(long, default_value = "icc")]
+    color_profile: ColorProfile,
+
+    /// The JPEG and lossy WebP quality, from 1 to 100.
+    #[clap(long, default_value_t = 90, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: u8,
+
+    /// The JPEG chroma subsampling: 444, 422 or 420.
+    #[clap(long, default_value = "420")]
+    subsampling: ChromaSubsampling,
+
+    /// Write WebP images losslessly.
+    #[clap(long)]
+    lossless: bool,
+
+    /// The OpenEXR compression: none, zip or piz.
+    #[clap(long, default_value = "zip")]
+    exr_compression: ExrCompression,
+
+    /// The color (#RRGGBB) transparent pixels are blended onto for formats without alpha.
+    #[clap(long, default_value = "#FFFFFF")]
+    background: Color,
+
+    /// Tone map FP16 captures saved to 8-bit formats with: clip, reinhard, aces or bt2390.
+    /// Captures in FP16 unless --capture-format is given.
+    #[clap(long)]
+    tone_map: Option<ToneMapOperator>,
+
+    /// The luminance, in nits, that tone mapping maps to SDR white.
+    #[clap(long, default_value_t = screenshot::SCRGB_WHITE_NITS, value_parser = parse_nits)]
+    sdr_white: f32,
+
+    /// Don't write the capture time, window, monitor, DPI or tool version into the image.
+    #[clap(long)]
+    pub no_metadata: bool,
+
+    /// Replace the output file if it already exists (default).
+    #[clap(long)]
+    overwrite: bool,
+
+    /// Fail instead of replacing an existing output file.
+    #[clap(long)]
+    no_clobber: bool,
+
+    /// Add -1, -2, ... to the output file name until it doesn't exist yet.
+    #[clap(long)]
+    unique: bool,
+
+    /// The output file that will contain the screenshot. Placeholders like {timestamp}, {date}
+    /// and {time} (in UTC), {title}, {process}, {monitor}, {width}, {height} and the screenshot
+    /// number {n:04} are filled in for each screenshot, e.g. shot-{n:04}-{title}.png. - writes
+    /// the image to stdout.
     #[clap(default_value = "screenshot.png")]
-    pub output_file: String,
+    pub output_file: FilenameTemplate,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print the windows or monitors that can be captured.
+    List {
+        /// What to list: windows or monitors.
+        kind: ListKind,
+
+        /// How to print the list: table, json or csv.
+        #[clap(long, default_value = "table")]
+        format: ListFormat,
+    },
 }
 
 pub enum CaptureMode {
     Window(String),
     Monitor(usize),
     Primary,
+    Region(Rect),
+    AllMonitors,
+    Foreground,
+    UnderCursor,
 }
 
 impl Args {
     pub fn parse_args() -> Self {
-        Self::parse()
+        Self::try_parse_args_from(std::env::args_os()).unwrap_or_else(|error| error.exit())
+    }
+
+    /// Parses `args`, including the checks clap can't express.
+    pub fn try_parse_args_from<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let args = Self::try_parse_from(args)?;
+        if args.writes_to_stdout() && args.output_format.is_none() {
+            return Err(Self::command().error(
+                ErrorKind::MissingRequiredArgument,
+                "writing to stdout (-) needs --output-format, as there's no extension",
+            ));
+        }
+        if args.schedule() != Schedule::once() && args.writes_to_stdout() {
+            return Err(Self::command().error(
+                ErrorKind::ArgumentConflict,
+                "--count and --until can't write to stdout (-), as the images would run together",
+            ));
+        }
+        if args.schedule() != Schedule::once()
+            && !args.output_file.varies_per_capture()
+            && args.collision() != Collision::Unique
+        {
+            return Err(Self::command().error(
+                ErrorKind::ArgumentConflict,
+                "--count and --until need an output file with {n} or {timestamp} in it, \
+                 e.g. shot-{n:04}.png, or --unique",
+            ));
+        }
+        Ok(args)
+    }
+
+    pub fn schedule(&self) -> Schedule {
+        if self.count.is_none() && self.until.is_none() {
+            return Schedule::once();
+        }
+        Schedule {
+            count: self.count.map(|count| count as usize),
+            interval: self.interval,
+            until: self.until,
+        }
     }
 
     pub fn capture_mode(&self) -> CaptureMode {
-        if let Some(window_query) = self.window.as_ref() {
-            CaptureMode::Window(window_query.clone())
+        let window_query = self.window.as_ref().or(self.wait_for_window.as_ref());
+        if window_query.is_some() || !self.window_filter().is_empty() {
+            CaptureMode::Window(window_query.cloned().unwrap_or_default())
         } else if let Some(index) = self.monitor {
             CaptureMode::Monitor(index)
+        } else if let Some(rect) = self.region {
+            CaptureMode::Region(rect)
+        } else if self.all_monitors {
+            CaptureMode::AllMonitors
+        } else if self.foreground {
+            CaptureMode::Foreground
+        } else if self.under_cursor {
+            CaptureMode::UnderCursor
         } else {
             CaptureMode::Primary
         }
     }
+
+    pub fn collision(&self) -> Collision {
+        if self.no_clobber {
+            Collision::NoClobber
+        } else if self.unique {
+            Collision::Unique
+        } else {
+            Collision::Overwrite
+        }
+    }
+
+    /// Whether the image is written to stdout instead of a file.
+    pub fn writes_to_stdout(&self) -> bool {
+        self.output_file.as_str() == "-"
+    }
+
+    pub fn waits_for_window(&self) -> bool {
+        self.wait_for_window.is_some()
+    }
+
+    pub fn window_filter(&self) -> WindowFilter {
+        WindowFilter {
+            pid: self.pid,
+            process_name: self.process.clone(),
+            class_name: self.class.clone(),
+            handle: self.hwnd,
+        }
+    }
+
+    pub fn output_format(&self) -> Option<ImageFormat> {
+        self.output_format
+            .or_else(|| ImageFormat::from_path(self.output_file.as_str()))
+    }
+
+    /// The pixel format to capture in for output stored as `output_pixel_format`.
+    /// Tone mapping needs an HDR capture to work from, and only makes sense for
+    /// 8-bit output.
+    pub fn capture_pixel_format(
+        &self,
+        output_pixel_format: PixelFormat,
+    ) -> screenshot::Result<PixelFormat> {
+        if self.tone_map_requested() && output_pixel_format == PixelFormat::Rgba16Float {
+            return Err(ScreenshotError::UnsupportedFormat(
+                "tone mapping only applies to 8-bit output, not rgba16f!".to_owned(),
+            ));
+        }
+        match (self.capture_format, self.tone_map_requested()) {
+            (Some(PixelFormat::Bgra8), true) => Err(ScreenshotError::UnsupportedFormat(
+                "tone mapping needs an rgba16f capture, not bgra8!".to_owned(),
+            )),
+            (Some(pixel_format), _) => Ok(pixel_format),
+            (None, true) => Ok(PixelFormat::Rgba16Float),
+            (None, false) => Ok(output_pixel_format),
+        }
+    }
+
+    pub fn tone_map_requested(&self) -> bool {
+        self.tone_map.is_some()
+    }
+
+    pub fn tone_map_options(&self) -> ToneMapOptions {
+        ToneMapOptions {
+            operator: self.tone_map.unwrap_or(ToneMapOperator::Clip),
+            sdr_white_nits: self.sdr_white,
+        }
+    }
+
+    /// The options for encoding, without metadata as that comes with the capture.
+    pub fn encode_options(&self) -> EncodeOptions {
+        EncodeOptions {
+            encoder: self.encoder,
+            png: PngOptions {
+                compression_level: self.png_compression,
+                filter: self.png_filter,
+                color_profile: self.color_profile,
+                bit_depth: self.png_bit_depth,
+                transfer: self.png_transfer,
+            },
+            jpeg: JpegOptions {
+                quality: self.quality,
+                subsampling: self.subsampling,
+                background: self.background,
+            },
+            webp: WebPOptions {
+                lossless: self.lossless,
+                quality: self.quality,
+            },
+            exr: ExrOptions {
+                compression: self.exr_compression,
+            },
+            metadata: None,
+        }
+    }
+}
+
+/// Parses durations like `500ms`, `3s`, `1.5m` or plain seconds.
+fn parse_duration(input: &str) -> Result<Duration, String> {
+    let (number, unit_seconds) = if let Some(number) = input.strip_suffix("ms") {
+        (number, 0.001)
+    } else if let Some(number) = input.strip_suffix('s') {
+        (number, 1.0)
+    } else if let Some(number) = input.strip_suffix('m') {
+        (number, 60.0)
+    } else {
+        (input, 1.0)
+    };
+    match number
+        .parse::<f64>()
+        .map(|number| Duration::try_from_secs_f64(number * unit_seconds))
+    {
+        Ok(Ok(duration)) => Ok(duration),
+        _ => Err(format!(
+            "'{}' isn't a duration, expecting e.g. 500ms, 3s or 2m",
+            input
+        )),
+    }
+}
+
+fn parse_nits(input: &str) -> Result<f32, String> {
+    match input.parse::<f32>() {
+        Ok(nits) if nits > 0.0 && nits.is_finite() => Ok(nits),
+        _ => Err(format!(
+            "'{}' isn't a luminance, expecting a number of nits above 0",
+            input
+        )),
+    }
+}
+
+fn parse_window_handle(input: &str) -> Result<isize, String> {
+    let parsed = match input
+        .strip_prefix("0x")
+        .or_else(|| input.strip_prefix("0X"))
+    {
+        Some(hex) => isize::from_str_radix(hex, 16),
+        None => input.parse(),
+    };
+    parsed.map_err(|_| {
+        format!(
+            "'{}' isn't a window handle, expecting 0x... or a number",
+            input
+        )
+    })
+}
+
+impl From<CaptureMode> for CaptureTarget {
+    fn from(mode: CaptureMode) -> Self {
+        match mode {
+            CaptureMode::Window(query) => CaptureTarget::Window(query),
+            CaptureMode::Monitor(index) => CaptureTarget::Monitor(index),
+            CaptureMode::Primary => CaptureTarget::Primary,
+            CaptureMode::Region(rect) => CaptureTarget::Region(rect),
+            CaptureMode::AllMonitors => CaptureTarget::AllMonitors,
+            CaptureMode::Foreground => CaptureTarget::Foreground,
+            CaptureMode::UnderCursor => CaptureTarget::UnderCursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, clap::Error> {
+        Args::try_parse_args_from(["screenshot"].iter().chain(args))
+    }
+
+    fn error_kind(args: &[&str]) -> ErrorKind {
+        parse(args).unwrap_err().kind()
+    }
+
+    /// The pixel format captured for `args`, or the exit code if they can't go together.
+    fn capture_pixel_format(args: &[&str]) -> Result<PixelFormat, i32> {
+        let args = parse(args).unwrap();
+        let output_pixel_format = args
+            .encode_options()
+            .pixel_format(args.output_format().unwrap());
+        args.capture_pixel_format(output_pixel_format)
+            .map_err(|error| error.exit_code())
+    }
+
+    #[test]
+    fn rejects_conflicting_targets() {
+        for args in [
+            &["--window", "Notepad", "--monitor", "1"][..],
+            &["--pid", "4", "--all-monitors"],
+            &["--primary", "--region", "0,0,8,8"],
+            &["--foreground", "--under-cursor"],
+            &["--no-clobber", "--unique"],
+        ] {
+            assert_eq!(error_kind(args), ErrorKind::ArgumentConflict, "{:?}", args);
+        }
+        assert_eq!(
+            error_kind(&["--match", "glob"]),
+            ErrorKind::MissingRequiredArgument
+        );
+        assert_eq!(
+            error_kind(&["--interval", "2s"]),
+            ErrorKind::MissingRequiredArgument
+        );
+    }
+
+    #[test]
+    fn selects_without_asking() {
+        let args = parse(&[
+            "--window",
+            "Notepad",
+            "--select",
+            "first",
+            "--non-interactive",
+        ])
+        .unwrap();
+        assert_eq!(args.select, Some(WindowSelection::First));
+        assert!(args.non_interactive);
+        assert!(parse(&["--process", "code.exe", "--select", "largest"]).is_ok());
+        assert_eq!(
+            error_kind(&["--select", "first"]),
+            ErrorKind::MissingRequiredArgument
+        );
+        assert_eq!(
+            error_kind(&["--primary", "--select", "first"]),
+            ErrorKind::MissingRequiredArgument
+        );
+    }
+
+    #[test]
+    fn checks_stdout_and_series() {
+        assert!(parse(&["--output-format", "png", "-"])
+            .unwrap()
+            .writes_to_stdout());
+        assert_eq!(error_kind(&["-"]), ErrorKind::MissingRequiredArgument);
+        for series in [["--count", "3"], ["--until", "1m"]] {
+            let mut args = vec!["--output-format", "png", "-"];
+            args.extend(series);
+            assert_eq!(error_kind(&args), ErrorKind::ArgumentConflict);
+
+            let mut args = vec!["shot.png"];
+            args.extend(series);
+            assert_eq!(error_kind(&args), ErrorKind::ArgumentConflict);
+            args.push("--unique");
+            assert!(parse(&args).is_ok());
+
+            let mut args = vec!["shot-{n:04}.png"];
+            args.extend(series);
+            assert!(parse(&args).is_ok());
+        }
+    }
+
+    #[test]
+    fn picks_capture_format_for_tone_mapping() {
+        assert_eq!(capture_pixel_format(&[]), Ok(PixelFormat::Bgra8));
+        assert_eq!(
+            capture_pixel_format(&["shot.exr"]),
+            Ok(PixelFormat::Rgba16Float)
+        );
+        assert_eq!(
+            capture_pixel_format(&["--capture-format", "rgba16f"]),
+            Ok(PixelFormat::Rgba16Float)
+        );
+        assert_eq!(
+            capture_pixel_format(&["--tone-map", "aces"]),
+            Ok(PixelFormat::Rgba16Float)
+        );
+        assert_eq!(
+            capture_pixel_format(&["--tone-map", "aces", "--capture-format", "rgba16f"]),
+            Ok(PixelFormat::Rgba16Float)
+        );
+        assert_eq!(
+            capture_pixel_format(&["--tone-map", "aces", "--capture-format", "bgra8"]),
+            Err(6)
+        );
+        assert_eq!(
+            capture_pixel_format(&["--tone-map", "clip", "shot.jxr"]),
+            Err(6)
+        );
+        assert_eq!(
+            capture_pixel_format(&["--tone-map", "clip", "--png-bit-depth", "16"]),
+            Err(6)
+        );
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("3"), Ok(Duration::from_secs(3)));
+        for input in ["", "-1s", "inf", "NaN", "1e300", "1e300m", "3h"] {
+            assert!(parse_duration(input).is_err(), "{}", input);
+        }
+    }
 }
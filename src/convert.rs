@@ -0,0 +1,109 @@
+use crate::frame::{Frame, PixelFormat};
+use crate::tonemap::{srgb_to_linear, tone_map, ToneMapOptions};
+use half::f16;
+
+/// Converts a frame to `pixel_format`:
+///
+/// | From      | To        | How                                          |
+/// |-----------|-----------|----------------------------------------------|
+/// | `rgba16f` | `bgra8`   | Tone mapped with `tone_map` (clip quantizes) |
+/// | `bgra8`   | `rgba16f` | sRGB linearized, SDR white becomes 1.0       |
+pub fn convert_frame(
+    frame: Frame,
+    pixel_format: PixelFormat,
+    tone_map_options: &ToneMapOptions,
+) -> Frame {
+    match (frame.format, pixel_format) {
+        (PixelFormat::Bgra8, PixelFormat::Bgra8)
+        | (PixelFormat::Rgba16Float, PixelFormat::Rgba16Float) => frame,
+        (PixelFormat::Rgba16Float, PixelFormat::Bgra8) => tone_map(&frame, tone_map_options),
+        (PixelFormat::Bgra8, PixelFormat::Rgba16Float) => linearize(&frame),
+    }
+}
+
+fn linearize(frame: &Frame) -> Frame {
+    let mut bytes = Vec::with_capacity(frame.bytes.len() * 2);
+    for pixel in frame.bytes.chunks_exact(4) {
+        let [b, g, r, a] = [pixel[0], pixel[1], pixel[2], pixel[3]];
+        let channels = [
+            srgb_to_linear(r as f32 / 255.0),
+            srgb_to_linear(g as f32 / 255.0),
+            srgb_to_linear(b as f32 / 255.0),
+            a as f32 / 255.0,
+        ];
+        for value in channels {
+            bytes.extend_from_slice(&f16::from_f32(value).to_le_bytes());
+        }
+    }
+    Frame::new(frame.width, frame.height, PixelFormat::Rgba16Float, bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::test_pattern;
+    use crate::tonemap::ToneMapOperator;
+
+    fn clip() -> ToneMapOptions {
+        ToneMapOptions {
+            operator: ToneMapOperator::Clip,
+            sdr_white_nits: crate::tonemap::SCRGB_WHITE_NITS,
+        }
+    }
+
+    fn channels(frame: &Frame) -> Vec<f32> {
+        frame
+            .bytes
+            .chunks_exact(2)
+            .map(|bytes| f16::from_le_bytes([bytes[0], bytes[1]]).to_f32())
+            .collect()
+    }
+
+    #[test]
+    fn linearizes_srgb() {
+        // BGRA pixels with a gray level of 0, 128 and 255
+        let bytes = [0, 0, 0, 255, 128, 128, 128, 128, 255, 255, 255, 0].to_vec();
+        let frame = Frame::new(3, 1, PixelFormat::Bgra8, bytes);
+        let frame = convert_frame(frame, PixelFormat::Rgba16Float, &clip());
+        assert_eq!(frame.format, PixelFormat::Rgba16Float);
+        assert_eq!((frame.width, frame.height), (3, 1));
+        let channels = channels(&frame);
+        assert_eq!(channels[0..4], [0.0, 0.0, 0.0, 1.0]);
+        for channel in &channels[4..7] {
+            assert!((channel - 0.2158).abs() < 0.001, "{}", channel);
+        }
+        assert!((channels[7] - 128.0 / 255.0).abs() < 0.001);
+        assert_eq!(channels[8..12], [1.0, 1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn quantizes_fp16() {
+        let mut bytes = Vec::new();
+        for value in [0.0f32, 0.2158, 1.0, 1.0, 4.0, 0.5, -1.0, 0.0] {
+            bytes.extend_from_slice(&f16::from_f32(value).to_le_bytes());
+        }
+        let frame = Frame::new(2, 1, PixelFormat::Rgba16Float, bytes);
+        let frame = convert_frame(frame, PixelFormat::Bgra8, &clip());
+        assert_eq!(frame.format, PixelFormat::Bgra8);
+        // Out of range channels are clipped, alpha is kept linear
+        assert_eq!(frame.bytes, [255, 128, 0, 255, 0, 188, 255, 0]);
+    }
+
+    #[test]
+    fn round_trips_bgra8() {
+        let frame = test_pattern(16, 16, PixelFormat::Bgra8);
+        let linear = convert_frame(frame.clone(), PixelFormat::Rgba16Float, &clip());
+        let round_tripped = convert_frame(linear, PixelFormat::Bgra8, &clip());
+        assert_eq!(round_tripped.bytes, frame.bytes);
+    }
+
+    #[test]
+    fn passes_same_format_through() {
+        for pixel_format in [PixelFormat::Bgra8, PixelFormat::Rgba16Float] {
+            let frame = test_pattern(8, 4, pixel_format);
+            let converted = convert_frame(frame.clone(), pixel_format, &clip());
+            assert_eq!(converted.format, pixel_format);
+            assert_eq!(converted.bytes, frame.bytes);
+        }
+    }
+}
@@ -1,42 +1,252 @@
-use windows::core::{Result, BOOL};
-use windows::Win32::Foundation::{LPARAM, RECT};
-use windows::Win32::Graphics::Gdi::{
-    EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITORINFO,
-};
+use crate::region::Rect;
 
-#[derive(Clone)]
+/// Whether a display can show HDR and wide color gamut content, and if it's
+/// turned on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdvancedColor {
+    Unsupported,
+    Supported,
+    Enabled,
+}
+
+#[derive(Clone, Debug)]
 pub struct DisplayInfo {
-    pub handle: HMONITOR,
+    /// The raw `HMONITOR` of the display.
+    pub handle: isize,
+    /// Where the display sits on the virtual desktop.
+    pub rect: Rect,
+    /// The part of `rect` not covered by the taskbar or docked toolbars.
+    pub work_area: Rect,
+    /// The GDI device name, e.g. `\\.\DISPLAY1`.
+    pub device_name: String,
+    /// The name of the connected monitor, e.g. `DELL U2720Q`, if Windows knows it.
+    pub friendly_name: Option<String>,
+    /// The effective dots per inch, 96 at 100% scaling.
+    pub dpi: u32,
+    pub is_primary: bool,
+    pub advanced_color: AdvancedColor,
 }
 
 impl DisplayInfo {
-    pub fn new(monitor_handle: HMONITOR) -> Result<Self> {
-        let mut info = MONITORINFO::default();
-        info.cbSize = std::mem::size_of::<MONITORINFO>() as u32;
+    /// The scale factor set for the display, e.g. 1.5 at 144 DPI.
+    pub fn scale(&self) -> f32 {
+        self.dpi as f32 / 96.0
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        self.advanced_color == AdvancedColor::Enabled
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::{AdvancedColor, DisplayInfo};
+    use crate::region::Rect;
+    use crate::{Result, ScreenshotError};
+    use windows::core::BOOL;
+    use windows::Win32::Devices::Display::{
+        DisplayConfigGetDeviceInfo, GetDisplayConfigBufferSizes, QueryDisplayConfig,
+        DISPLAYCONFIG_DEVICE_INFO_GET_ADVANCED_COLOR_INFO,
+        DISPLAYCONFIG_DEVICE_INFO_GET_SOURCE_NAME, DISPLAYCONFIG_DEVICE_INFO_GET_TARGET_NAME,
+        DISPLAYCONFIG_DEVICE_INFO_HEADER, DISPLAYCONFIG_DEVICE_INFO_TYPE,
+        DISPLAYCONFIG_GET_ADVANCED_COLOR_INFO, DISPLAYCONFIG_MODE_INFO, DISPLAYCONFIG_PATH_INFO,
+        DISPLAYCONFIG_SOURCE_DEVICE_NAME, DISPLAYCONFIG_TARGET_DEVICE_NAME, QDC_ONLY_ACTIVE_PATHS,
+    };
+    use windows::Win32::Foundation::{LPARAM, LUID, RECT};
+    use windows::Win32::Graphics::Gdi::{
+        EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITORINFOEXW,
+    };
+    use windows::Win32::UI::HiDpi::{
+        GetDpiForMonitor, SetThreadDpiAwarenessContext, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+        MDT_EFFECTIVE_DPI,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::MONITORINFOF_PRIMARY;
+
+    impl DisplayInfo {
+        pub fn new(monitor_handle: HMONITOR) -> Result<Self> {
+            let mut info = MONITORINFOEXW::default();
+            info.monitorInfo.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
 
+            unsafe {
+                GetMonitorInfoW(monitor_handle, &mut info as *mut _ as *mut _).ok()?;
+            }
+
+            let device_name = from_wide(&info.szDevice);
+            let (friendly_name, advanced_color) =
+                display_target(&device_name).unwrap_or((None, AdvancedColor::Unsupported));
+            Ok(Self {
+                handle: monitor_handle.0 as isize,
+                rect: to_rect(&info.monitorInfo.rcMonitor),
+                work_area: to_rect(&info.monitorInfo.rcWork),
+                device_name,
+                friendly_name,
+                dpi: monitor_dpi(monitor_handle)?,
+                is_primary: info.monitorInfo.dwFlags & MONITORINFOF_PRIMARY != 0,
+                advanced_color,
+            })
+        }
+
+        pub fn hmonitor(&self) -> HMONITOR {
+            HMONITOR(self.handle as *mut _)
+        }
+    }
+
+    /// The effective DPI of a monitor. Queried as a per-monitor aware thread, as
+    /// DPI unaware processes are always told 96.
+    pub fn monitor_dpi(monitor_handle: HMONITOR) -> Result<u32> {
         unsafe {
-            GetMonitorInfoW(monitor_handle, &mut info as *mut _ as *mut _).ok()?;
+            let previous = SetThreadDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2);
+            let (mut dpi_x, mut dpi_y) = (0, 0);
+            let result =
+                GetDpiForMonitor(monitor_handle, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y);
+            SetThreadDpiAwarenessContext(previous);
+            result?;
+            Ok(dpi_x)
         }
+    }
+
+    /// Finds the monitor the GDI device `device_name` drives and reads its name
+    /// and advanced color state. Returns `None` if the display configuration
+    /// can't be queried, which happens in some remote sessions.
+    fn display_target(device_name: &str) -> Option<(Option<String>, AdvancedColor)> {
+        unsafe {
+            let (mut path_count, mut mode_count) = (0, 0);
+            GetDisplayConfigBufferSizes(QDC_ONLY_ACTIVE_PATHS, &mut path_count, &mut mode_count)
+                .ok()
+                .ok()?;
+            let mut paths = vec![DISPLAYCONFIG_PATH_INFO::default(); path_count as usize];
+            let mut modes = vec![DISPLAYCONFIG_MODE_INFO::default(); mode_count as usize];
+            QueryDisplayConfig(
+                QDC_ONLY_ACTIVE_PATHS,
+                &mut path_count,
+                paths.as_mut_ptr(),
+                &mut mode_count,
+                modes.as_mut_ptr(),
+                None,
+            )
+            .ok()
+            .ok()?;
+            paths.truncate(path_count as usize);
+
+            let path = paths.iter().find(|path| {
+                let mut source = DISPLAYCONFIG_SOURCE_DEVICE_NAME {
+                    header: header::<DISPLAYCONFIG_SOURCE_DEVICE_NAME>(
+                        DISPLAYCONFIG_DEVICE_INFO_GET_SOURCE_NAME,
+                        path.sourceInfo.adapterId,
+                        path.sourceInfo.id,
+                    ),
+                    ..Default::default()
+                };
+                DisplayConfigGetDeviceInfo(&mut source.header) == 0
+                    && from_wide(&source.viewGdiDeviceName) == device_name
+            })?;
+            let (adapter_id, target_id) = (path.targetInfo.adapterId, path.targetInfo.id);
+
+            let mut target = DISPLAYCONFIG_TARGET_DEVICE_NAME {
+                header: header::<DISPLAYCONFIG_TARGET_DEVICE_NAME>(
+                    DISPLAYCONFIG_DEVICE_INFO_GET_TARGET_NAME,
+                    adapter_id,
+                    target_id,
+                ),
+                ..Default::default()
+            };
+            let friendly_name = if DisplayConfigGetDeviceInfo(&mut target.header) == 0 {
+                Some(from_wide(&target.monitorFriendlyDeviceName)).filter(|name| !name.is_empty())
+            } else {
+                None
+            };
 
-        Ok(Self {
-            handle: monitor_handle,
-        })
+            let mut color = DISPLAYCONFIG_GET_ADVANCED_COLOR_INFO {
+                header: header::<DISPLAYCONFIG_GET_ADVANCED_COLOR_INFO>(
+                    DISPLAYCONFIG_DEVICE_INFO_GET_ADVANCED_COLOR_INFO,
+                    adapter_id,
+                    target_id,
+                ),
+                ..Default::default()
+            };
+            let advanced_color = if DisplayConfigGetDeviceInfo(&mut color.header) != 0 {
+                AdvancedColor::Unsupported
+            } else {
+                // Bit 0 is advancedColorSupported, bit 1 advancedColorEnabled
+                match color.Anonymous.value & 0b11 {
+                    0 => AdvancedColor::Unsupported,
+                    1 => AdvancedColor::Supported,
+                    _ => AdvancedColor::Enabled,
+                }
+            };
+
+            Some((friendly_name, advanced_color))
+        }
     }
-}
 
-pub fn enumerate_displays() -> Result<Vec<DisplayInfo>> {
-    unsafe {
-        let displays = Box::into_raw(Box::default());
-        EnumDisplayMonitors(None, None, Some(enum_monitor), LPARAM(displays as isize)).ok()?;
-        Ok(*Box::from_raw(displays))
+    fn header<T>(
+        info_type: DISPLAYCONFIG_DEVICE_INFO_TYPE,
+        adapter_id: LUID,
+        id: u32,
+    ) -> DISPLAYCONFIG_DEVICE_INFO_HEADER {
+        DISPLAYCONFIG_DEVICE_INFO_HEADER {
+            r#type: info_type,
+            size: std::mem::size_of::<T>() as u32,
+            adapterId: adapter_id,
+            id,
+        }
+    }
+
+    fn to_rect(rect: &RECT) -> Rect {
+        Rect::new(
+            rect.left,
+            rect.top,
+            (rect.right - rect.left) as u32,
+            (rect.bottom - rect.top) as u32,
+        )
+    }
+
+    fn from_wide(chars: &[u16]) -> String {
+        let len = chars.iter().position(|c| *c == 0).unwrap_or(chars.len());
+        String::from_utf16_lossy(&chars[..len])
     }
-}
 
-extern "system" fn enum_monitor(monitor: HMONITOR, _: HDC, _: *mut RECT, state: LPARAM) -> BOOL {
-    unsafe {
-        let state = Box::leak(Box::from_raw(state.0 as *mut Vec<DisplayInfo>));
-        let display_info = DisplayInfo::new(monitor).unwrap();
-        state.push(display_info);
+    #[derive(Default)]
+    struct DisplayEnumerationState {
+        displays: Vec<DisplayInfo>,
+        error: Option<ScreenshotError>,
+    }
+
+    pub fn enumerate_displays() -> Result<Vec<DisplayInfo>> {
+        unsafe {
+            let state = Box::into_raw(Box::<DisplayEnumerationState>::default());
+            let result =
+                EnumDisplayMonitors(None, None, Some(enum_monitor), LPARAM(state as isize));
+            let state = Box::from_raw(state);
+            if let Some(error) = state.error {
+                return Err(error);
+            }
+            result.ok()?;
+            Ok(state.displays)
+        }
+    }
+
+    extern "system" fn enum_monitor(
+        monitor: HMONITOR,
+        _: HDC,
+        _: *mut RECT,
+        state: LPARAM,
+    ) -> BOOL {
+        unsafe {
+            let state = Box::leak(Box::from_raw(state.0 as *mut DisplayEnumerationState));
+            match DisplayInfo::new(monitor) {
+                Ok(display_info) => state.displays.push(display_info),
+                Err(error) => {
+                    state.error = Some(error);
+                    return false.into();
+                }
+            }
+        }
+        true.into()
     }
-    true.into()
 }
+
+#[cfg(windows)]
+pub use platform::enumerate_displays;
+#[cfg(windows)]
+pub(crate) use platform::monitor_dpi;
@@ -0,0 +1,124 @@
+use crate::frame::{Frame, PixelFormat};
+use crate::{Result, ScreenshotError};
+use exr::prelude::{Encoding, Image, Layer, LayerAttributes, SpecificChannels, WritableImage};
+use half::f16;
+use std::io::{Cursor, Write};
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExrCompression {
+    None,
+    Zip,
+    Piz,
+}
+
+impl FromStr for ExrCompression {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "none" => Ok(ExrCompression::None),
+            "zip" => Ok(ExrCompression::Zip),
+            "piz" => Ok(ExrCompression::Piz),
+            _ => Err(format!(
+                "'{}' isn't an EXR compression, expecting none, zip or piz",
+                input
+            )),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ExrOptions {
+    pub compression: ExrCompression,
+}
+
+impl Default for ExrOptions {
+    fn default() -> Self {
+        Self {
+            compression: ExrCompression::Zip,
+        }
+    }
+}
+
+/// Encodes an FP16 frame as a half-float RGBA OpenEXR image. Values are written
+/// as captured, i.e. linear scRGB where 1.0 is 80 nits.
+pub fn encode_exr<W: Write>(frame: &Frame, options: &ExrOptions, mut writer: W) -> Result<()> {
+    if frame.format != PixelFormat::Rgba16Float {
+        return Err(ScreenshotError::UnsupportedFormat(format!(
+            "EXR is only written from {:?} captures, not {:?}!",
+            PixelFormat::Rgba16Float,
+            frame.format
+        )));
+    }
+
+    let pixels: Vec<[f16; 4]> = frame.rgba16f_pixels().collect();
+    let width = frame.width as usize;
+    let compression = match options.compression {
+        ExrCompression::None => exr::compression::Compression::Uncompressed,
+        ExrCompression::Zip => exr::compression::Compression::ZIP16,
+        ExrCompression::Piz => exr::compression::Compression::PIZ,
+    };
+    let layer = Layer::new(
+        (width, frame.height as usize),
+        LayerAttributes::default(),
+        Encoding {
+            compression,
+            ..Encoding::default()
+        },
+        SpecificChannels::rgba(|position: exr::math::Vec2<usize>| {
+            let [r, g, b, a] = pixels[position.y() * width + position.x()];
+            (r, g, b, a)
+        }),
+    );
+
+    // The EXR writer needs to seek, so encode into memory first.
+    let mut buffer = Cursor::new(Vec::new());
+    Image::from_layer(layer)
+        .write()
+        .to_buffered(&mut buffer)
+        .map_err(|error| match error {
+            exr::error::Error::Io(error) => ScreenshotError::Io(error),
+            error => ScreenshotError::Encode(error.to_string()),
+        })?;
+    writer.write_all(buffer.get_ref())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::test_pattern;
+    use exr::prelude::{ReadChannels, ReadLayers};
+
+    #[test]
+    fn round_trips_halves() {
+        let frame = test_pattern(13, 7, PixelFormat::Rgba16Float);
+        for compression in [
+            ExrCompression::None,
+            ExrCompression::Zip,
+            ExrCompression::Piz,
+        ] {
+            let mut bytes = Vec::new();
+            encode_exr(&frame, &ExrOptions { compression }, &mut bytes).unwrap();
+
+            let image = exr::prelude::read()
+                .no_deep_data()
+                .largest_resolution_level()
+                .rgba_channels(
+                    |resolution, _| vec![[f16::ZERO; 4]; resolution.width() * resolution.height()],
+                    |pixels: &mut Vec<[f16; 4]>, position, (r, g, b, a): (f16, f16, f16, f16)| {
+                        pixels[position.y() * 13 + position.x()] = [r, g, b, a]
+                    },
+                )
+                .first_valid_layer()
+                .all_attributes()
+                .from_buffered(Cursor::new(&bytes))
+                .unwrap();
+            let size = image.layer_data.size;
+            assert_eq!((size.width(), size.height()), (13, 7));
+            let expected: Vec<_> = frame.rgba16f_pixels().collect();
+            assert_eq!(image.layer_data.channel_data.pixels, expected);
+        }
+    }
+}
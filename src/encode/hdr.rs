@@ -0,0 +1,208 @@
+use crate::frame::{Frame, PixelFormat};
+use crate::{Result, ScreenshotError};
+use half::f16;
+use std::io::Write;
+
+/// Encodes an FP16 frame as a Radiance RGBE image. Values are written as
+/// captured (linear scRGB), negative and NaN values are clamped to 0, infinite
+/// ones to the largest FP16 value, and alpha is dropped.
+pub fn encode_hdr<W: Write>(frame: &Frame, mut writer: W) -> Result<()> {
+    if frame.format != PixelFormat::Rgba16Float {
+        return Err(ScreenshotError::UnsupportedFormat(format!(
+            "HDR is only written from {:?} captures, not {:?}!",
+            PixelFormat::Rgba16Float,
+            frame.format
+        )));
+    }
+    if frame.width == 0 || frame.height == 0 {
+        return Err(ScreenshotError::UnsupportedFormat(format!(
+            "HDR can't hold an empty {}x{} image!",
+            frame.width, frame.height
+        )));
+    }
+
+    write!(
+        writer,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        frame.height, frame.width
+    )?;
+
+    let pixels: Vec<[u8; 4]> = frame
+        .rgba16f_pixels()
+        .map(|[r, g, b, _]| to_rgbe(r.to_f32(), g.to_f32(), b.to_f32()))
+        .collect();
+    let mut scanline = Vec::new();
+    for row in pixels.chunks_exact(frame.width as usize) {
+        scanline.clear();
+        write_scanline(&mut scanline, row);
+        writer.write_all(&scanline)?;
+    }
+    Ok(())
+}
+
+fn to_rgbe(r: f32, g: f32, b: f32) -> [u8; 4] {
+    // Negative and NaN channels become 0, infinite ones the brightest FP16 value
+    let clamp = |value: f32| value.max(0.0).min(f16::MAX.to_f32());
+    let (r, g, b) = (clamp(r), clamp(g), clamp(b));
+    let max = r.max(g).max(b);
+    if max < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    // max = mantissa * 2^exponent with mantissa in [0.5, 1)
+    let exponent = max.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2f32.powi(exponent);
+    let channel = |value: f32| (value * scale).min(255.0) as u8;
+    [
+        channel(r),
+        channel(g),
+        channel(b),
+        (exponent + 128).clamp(0, 255) as u8,
+    ]
+}
+
+/// Writes one scanline, run-length encoded if the width allows it.
+fn write_scanline(output: &mut Vec<u8>, row: &[[u8; 4]]) {
+    let width = row.len();
+    if !(8..0x8000).contains(&width) {
+        output.extend(row.iter().flatten());
+        return;
+    }
+
+    output.extend_from_slice(&[2, 2, (width >> 8) as u8, width as u8]);
+    let mut component = Vec::with_capacity(width);
+    for channel in 0..4 {
+        component.clear();
+        component.extend(row.iter().map(|pixel| pixel[channel]));
+        write_rle(output, &component);
+    }
+}
+
+fn write_rle(output: &mut Vec<u8>, data: &[u8]) {
+    const MIN_RUN: usize = 4;
+    let mut literal_start = 0;
+    let mut i = 0;
+    while i < data.len() {
+        let run = data[i..]
+            .iter()
+            .take(127)
+            .take_while(|value| **value == data[i])
+            .count();
+        if run >= MIN_RUN {
+            write_literals(output, &data[literal_start..i]);
+            output.push(128 + run as u8);
+            output.push(data[i]);
+            i += run;
+            literal_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    write_literals(output, &data[literal_start..]);
+}
+
+fn write_literals(output: &mut Vec<u8>, mut data: &[u8]) {
+    while !data.is_empty() {
+        let count = data.len().min(128);
+        output.push(count as u8);
+        output.extend_from_slice(&data[..count]);
+        data = &data[count..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use half::f16;
+
+    fn hdr_frame(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [f32; 3]) -> Frame {
+        let mut bytes = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let [r, g, b] = pixel(x, y);
+                for value in [r, g, b, 1.0] {
+                    bytes.extend_from_slice(&f16::from_f32(value).to_le_bytes());
+                }
+            }
+        }
+        Frame::new(width, height, PixelFormat::Rgba16Float, bytes)
+    }
+
+    /// Reads back the pixels of an image written by `encode_hdr`.
+    fn decode(bytes: &[u8], width: usize, height: usize) -> Vec<[u8; 4]> {
+        let header = format!(
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+            height, width
+        );
+        assert!(bytes.starts_with(header.as_bytes()));
+        let mut data = &bytes[header.len()..];
+        let mut pixels = Vec::new();
+        for _ in 0..height {
+            assert_eq!(data[..4], [2, 2, (width >> 8) as u8, width as u8]);
+            data = &data[4..];
+            let mut row = vec![[0u8; 4]; width];
+            for channel in 0..4 {
+                let mut x = 0;
+                while x < width {
+                    let count = data[0] as usize;
+                    if count > 128 {
+                        for pixel in &mut row[x..x + count - 128] {
+                            pixel[channel] = data[1];
+                        }
+                        x += count - 128;
+                        data = &data[2..];
+                    } else {
+                        for (pixel, value) in row[x..x + count].iter_mut().zip(&data[1..]) {
+                            pixel[channel] = *value;
+                        }
+                        x += count;
+                        data = &data[1 + count..];
+                    }
+                }
+            }
+            pixels.extend(row);
+        }
+        assert!(data.is_empty());
+        pixels
+    }
+
+    #[test]
+    fn converts_to_rgbe() {
+        assert_eq!(to_rgbe(1.0, 1.0, 1.0), [128, 128, 128, 129]);
+        assert_eq!(to_rgbe(0.5, 0.25, 0.0), [128, 64, 0, 128]);
+        assert_eq!(to_rgbe(-1.0, 0.0, 0.0), [0, 0, 0, 0]);
+        assert_eq!(to_rgbe(f32::NAN, f32::NEG_INFINITY, 0.0), [0, 0, 0, 0]);
+        assert_eq!(
+            to_rgbe(f32::INFINITY, 0.0, 0.0),
+            to_rgbe(f16::MAX.to_f32(), 0.0, 0.0)
+        );
+        assert_eq!(to_rgbe(f32::INFINITY, 0.0, 0.0), [255, 0, 0, 144]);
+    }
+
+    #[test]
+    fn rejects_empty_frames() {
+        for (width, height) in [(0, 0), (0, 4), (4, 0)] {
+            let frame = Frame::new(width, height, PixelFormat::Rgba16Float, Vec::new());
+            let result = encode_hdr(&frame, Vec::new());
+            assert!(matches!(result, Err(ScreenshotError::UnsupportedFormat(_))));
+        }
+    }
+
+    #[test]
+    fn round_trips_run_length_encoding() {
+        let frame = hdr_frame(300, 3, |x, y| {
+            if x < 150 {
+                [1.0, 1.0, 1.0]
+            } else {
+                [x as f32 / 300.0, y as f32, 4.0]
+            }
+        });
+        let mut bytes = Vec::new();
+        encode_hdr(&frame, &mut bytes).unwrap();
+
+        let expected: Vec<_> = frame
+            .rgba16f_pixels()
+            .map(|[r, g, b, _]| to_rgbe(r.to_f32(), g.to_f32(), b.to_f32()))
+            .collect();
+        assert_eq!(decode(&bytes, 300, 3), expected);
+    }
+}
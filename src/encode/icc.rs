@@ -0,0 +1,181 @@
+//! Builds a compact ICC v4 display profile for sRGB, so PNG files can carry an
+//! iCCP chunk without shipping a profile alongside the binary.
+
+/// The sRGB primaries, chromatically adapted to the D50 profile connection space.
+const RED: [f64; 3] = [0.436_074_7, 0.222_504_5, 0.013_932_2];
+const GREEN: [f64; 3] = [0.385_064_9, 0.716_878_6, 0.097_104_5];
+const BLUE: [f64; 3] = [0.143_080_4, 0.060_616_9, 0.714_173_3];
+const D50: [f64; 3] = [0.964_2, 1.0, 0.824_9];
+/// Bradford adaptation from D65 to D50.
+const CHAD: [f64; 9] = [
+    1.047_811_2,
+    0.022_886_6,
+    -0.050_127_0,
+    0.029_542_4,
+    0.990_484_4,
+    -0.017_049_1,
+    -0.009_234_5,
+    0.015_043_6,
+    0.752_131_6,
+];
+
+/// An sRGB ICC profile using a parametric curve for the transfer function.
+pub fn srgb_icc_profile() -> Vec<u8> {
+    let description = mluc("sRGB IEC61966-2.1");
+    let copyright = mluc("No copyright, use freely");
+    let white_point = xyz(D50);
+    let red = xyz(RED);
+    let green = xyz(GREEN);
+    let blue = xyz(BLUE);
+    let chad = sf32(&CHAD);
+    let trc = srgb_parametric_curve();
+    let tags: [(&[u8; 4], &[u8]); 10] = [
+        (b"desc", &description),
+        (b"cprt", &copyright),
+        (b"wtpt", &white_point),
+        (b"chad", &chad),
+        (b"rXYZ", &red),
+        (b"gXYZ", &green),
+        (b"bXYZ", &blue),
+        (b"rTRC", &trc),
+        (b"gTRC", &trc),
+        (b"bTRC", &trc),
+    ];
+
+    let table_end = 128 + 4 + tags.len() * 12;
+    let mut table = Vec::new();
+    let mut data: Vec<u8> = Vec::new();
+    let mut written: Vec<(&[u8], u32)> = Vec::new();
+    for (signature, tag) in tags {
+        // Tags with the same contents share their data
+        let offset = match written.iter().find(|(contents, _)| *contents == tag) {
+            Some((_, offset)) => *offset,
+            None => {
+                let offset = (table_end + data.len()) as u32;
+                data.extend_from_slice(tag);
+                pad_to_four(&mut data);
+                written.push((tag, offset));
+                offset
+            }
+        };
+        table.extend_from_slice(signature);
+        table.extend_from_slice(&offset.to_be_bytes());
+        table.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+    }
+
+    let size = (table_end + data.len()) as u32;
+    let mut profile = Vec::with_capacity(size as usize);
+    profile.extend_from_slice(&size.to_be_bytes());
+    profile.extend_from_slice(&[0; 4]); // Preferred CMM
+    profile.extend_from_slice(&[4, 0x30, 0, 0]); // Version 4.3
+    profile.extend_from_slice(b"mntr");
+    profile.extend_from_slice(b"RGB ");
+    profile.extend_from_slice(b"XYZ ");
+    for value in [2024u16, 1, 1, 0, 0, 0] {
+        profile.extend_from_slice(&value.to_be_bytes());
+    }
+    profile.extend_from_slice(b"acsp");
+    profile.extend_from_slice(&[0; 24]); // Platform, flags, manufacturer, model, attributes
+    profile.extend_from_slice(&0u32.to_be_bytes()); // Perceptual intent
+    profile.extend_from_slice(&white_point[8..]);
+    profile.extend_from_slice(&[0; 4]); // Creator
+    profile.extend_from_slice(&[0; 16]); // Profile id, optional
+    profile.extend_from_slice(&[0; 28]);
+    debug_assert_eq!(profile.len(), 128);
+    profile.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+    profile.extend_from_slice(&table);
+    profile.extend_from_slice(&data);
+    profile
+}
+
+fn s15_fixed16(value: f64) -> [u8; 4] {
+    ((value * 65536.0).round() as i32).to_be_bytes()
+}
+
+fn xyz(value: [f64; 3]) -> Vec<u8> {
+    sf32_tagged(b"XYZ ", &value)
+}
+
+fn sf32(values: &[f64]) -> Vec<u8> {
+    sf32_tagged(b"sf32", values)
+}
+
+fn sf32_tagged(signature: &[u8; 4], values: &[f64]) -> Vec<u8> {
+    let mut tag = signature.to_vec();
+    tag.extend_from_slice(&[0; 4]);
+    for value in values {
+        tag.extend_from_slice(&s15_fixed16(*value));
+    }
+    tag
+}
+
+/// The IEC 61966-2-1 curve as parametric function type 3:
+/// `Y = (aX + b)^g` for `X >= d`, `Y = cX` otherwise.
+fn srgb_parametric_curve() -> Vec<u8> {
+    let mut tag = b"para".to_vec();
+    tag.extend_from_slice(&[0; 4]);
+    tag.extend_from_slice(&3u16.to_be_bytes());
+    tag.extend_from_slice(&[0; 2]);
+    for value in [2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.040_45] {
+        tag.extend_from_slice(&s15_fixed16(value));
+    }
+    tag
+}
+
+/// A multi-localized unicode tag with a single en-US record.
+fn mluc(text: &str) -> Vec<u8> {
+    let text: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    let mut tag = b"mluc".to_vec();
+    tag.extend_from_slice(&[0; 4]);
+    tag.extend_from_slice(&1u32.to_be_bytes()); // Record count
+    tag.extend_from_slice(&12u32.to_be_bytes()); // Record size
+    tag.extend_from_slice(b"enUS");
+    tag.extend_from_slice(&(text.len() as u32).to_be_bytes());
+    tag.extend_from_slice(&28u32.to_be_bytes());
+    tag.extend_from_slice(&text);
+    tag
+}
+
+fn pad_to_four(data: &mut Vec<u8>) {
+    while !data.len().is_multiple_of(4) {
+        data.push(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn builds_valid_profile() {
+        let profile = srgb_icc_profile();
+        assert_eq!(read_u32(&profile, 0) as usize, profile.len());
+        assert_eq!(&profile[36..40], b"acsp");
+        assert_eq!(&profile[12..24], b"mntrRGB XYZ ");
+
+        let count = read_u32(&profile, 128) as usize;
+        let mut signatures = Vec::new();
+        for i in 0..count {
+            let entry = 132 + i * 12;
+            let (offset, size) = (read_u32(&profile, entry + 4), read_u32(&profile, entry + 8));
+            assert_eq!(offset % 4, 0);
+            assert!((offset + size) as usize <= profile.len());
+            signatures.push(&profile[entry..entry + 4]);
+        }
+        assert!(signatures.contains(&&b"desc"[..]));
+        assert!(signatures.contains(&&b"rTRC"[..]));
+
+        // All three curves point at the same data
+        let trc_offsets: Vec<_> = (0..count)
+            .map(|i| 132 + i * 12)
+            .filter(|entry| profile[entry + 1..entry + 4] == *b"TRC")
+            .map(|entry| read_u32(&profile, entry + 4))
+            .collect();
+        assert_eq!(trc_offsets.len(), 3);
+        assert!(trc_offsets.iter().all(|offset| *offset == trc_offsets[0]));
+    }
+}
@@ -0,0 +1,228 @@
+use crate::frame::{Frame, PixelFormat};
+use crate::{Result, ScreenshotError};
+use std::io::Write;
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChromaSubsampling {
+    /// Full resolution chroma.
+    Yuv444,
+    /// Half horizontal chroma resolution.
+    Yuv422,
+    /// Half horizontal and vertical chroma resolution.
+    Yuv420,
+}
+
+impl FromStr for ChromaSubsampling {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "444" => Ok(ChromaSubsampling::Yuv444),
+            "422" => Ok(ChromaSubsampling::Yuv422),
+            "420" => Ok(ChromaSubsampling::Yuv420),
+            _ => Err(format!(
+                "'{}' isn't a chroma subsampling mode, expecting 444, 422 or 420",
+                input
+            )),
+        }
+    }
+}
+
+/// An opaque sRGB color.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const WHITE: Color = Color {
+        r: 255,
+        g: 255,
+        b: 255,
+    };
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    /// Parses `RRGGBB` or `#RRGGBB`.
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        let hex = input.strip_prefix('#').unwrap_or(input);
+        let invalid = || format!("'{}' isn't a color, expecting #RRGGBB", input);
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+        Ok(Color {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct JpegOptions {
+    /// From 1 (smallest file) to 100 (best quality).
+    pub quality: u8,
+    pub subsampling: ChromaSubsampling,
+    /// JPEG has no alpha channel, so transparent pixels are blended onto this color.
+    pub background: Color,
+}
+
+impl Default for JpegOptions {
+    fn default() -> Self {
+        Self {
+            quality: 90,
+            subsampling: ChromaSubsampling::Yuv420,
+            background: Color::WHITE,
+        }
+    }
+}
+
+/// Encodes a BGRA8 frame as a baseline JPEG.
+pub fn encode_jpeg<W: Write>(frame: &Frame, options: &JpegOptions, writer: W) -> Result<()> {
+    if frame.format != PixelFormat::Bgra8 {
+        return Err(ScreenshotError::UnsupportedFormat(format!(
+            "JPEG can't hold {:?} pixels!",
+            frame.format
+        )));
+    }
+    if !(1..=100).contains(&options.quality) {
+        return Err(ScreenshotError::Encode(format!(
+            "JPEG quality {} is out of range (1-100)!",
+            options.quality
+        )));
+    }
+    let (width, height) = match (u16::try_from(frame.width), u16::try_from(frame.height)) {
+        (Ok(width), Ok(height)) => (width, height),
+        _ => {
+            return Err(ScreenshotError::Encode(format!(
+                "{}x{} is too large for JPEG!",
+                frame.width, frame.height
+            )))
+        }
+    };
+
+    let mut encoder = jpeg_encoder::Encoder::new(writer, options.quality);
+    encoder.set_sampling_factor(match options.subsampling {
+        ChromaSubsampling::Yuv444 => jpeg_encoder::SamplingFactor::R_4_4_4,
+        ChromaSubsampling::Yuv422 => jpeg_encoder::SamplingFactor::R_4_2_2,
+        ChromaSubsampling::Yuv420 => jpeg_encoder::SamplingFactor::R_4_2_0,
+    });
+    let bgr = flatten_alpha(&frame.bytes, options.background);
+    encoder
+        .encode(&bgr, width, height, jpeg_encoder::ColorType::Bgr)
+        .map_err(|error| match error {
+            jpeg_encoder::EncodingError::IoError(error) => ScreenshotError::Io(error),
+            error => ScreenshotError::Encode(error.to_string()),
+        })
+}
+
+/// Blends BGRA8 pixels onto `background`, producing BGR8.
+pub fn flatten_alpha(bytes: &[u8], background: Color) -> Vec<u8> {
+    let blend = |value: u8, background: u8, alpha: u8| {
+        let value = value as u32 * alpha as u32 + background as u32 * (255 - alpha as u32);
+        ((value + 127) / 255) as u8
+    };
+    let mut bgr = Vec::with_capacity(bytes.len() / 4 * 3);
+    for pixel in bytes.chunks_exact(4) {
+        let alpha = pixel[3];
+        bgr.push(blend(pixel[0], background.b, alpha));
+        bgr.push(blend(pixel[1], background.g, alpha));
+        bgr.push(blend(pixel[2], background.r, alpha));
+    }
+    bgr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::test_pattern;
+
+    /// A smooth gradient, which JPEG can reproduce closely.
+    fn gradient(width: u32, height: u32) -> Frame {
+        let mut bytes = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                bytes.extend_from_slice(&[(x * 4) as u8, (y * 4) as u8, 128, 255]);
+            }
+        }
+        Frame::new(width, height, PixelFormat::Bgra8, bytes)
+    }
+
+    fn encode(frame: &Frame, quality: u8, subsampling: ChromaSubsampling) -> Vec<u8> {
+        let options = JpegOptions {
+            quality,
+            subsampling,
+            ..Default::default()
+        };
+        let mut bytes = Vec::new();
+        encode_jpeg(frame, &options, &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trips() {
+        let frame = gradient(48, 32);
+        let bytes = encode(&frame, 95, ChromaSubsampling::Yuv444);
+
+        let mut decoder = jpeg_decoder::Decoder::new(bytes.as_slice());
+        let rgb = decoder.decode().unwrap();
+        let info = decoder.info().unwrap();
+        assert_eq!((info.width, info.height), (48, 32));
+        assert_eq!(info.pixel_format, jpeg_decoder::PixelFormat::RGB24);
+        for (bgra, rgb) in frame.bytes.chunks_exact(4).zip(rgb.chunks_exact(3)) {
+            let expected = [bgra[2], bgra[1], bgra[0]];
+            for (expected, actual) in expected.iter().zip(rgb) {
+                assert!(
+                    expected.abs_diff(*actual) <= 4,
+                    "{:?} came back as {:?}",
+                    expected,
+                    rgb
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn quality_and_subsampling_change_output() {
+        let frame = test_pattern(64, 64, PixelFormat::Bgra8);
+        let best = encode(&frame, 95, ChromaSubsampling::Yuv444);
+        let low_quality = encode(&frame, 30, ChromaSubsampling::Yuv444);
+        let subsampled = encode(&frame, 95, ChromaSubsampling::Yuv420);
+        let half_subsampled = encode(&frame, 95, ChromaSubsampling::Yuv422);
+        assert!(low_quality.len() < best.len());
+        assert!(subsampled.len() < half_subsampled.len());
+        assert!(half_subsampled.len() < best.len());
+    }
+
+    #[test]
+    fn rejects_fp16_frames() {
+        let frame = test_pattern(4, 4, PixelFormat::Rgba16Float);
+        let result = encode_jpeg(&frame, &JpegOptions::default(), Vec::new());
+        assert!(matches!(result, Err(ScreenshotError::UnsupportedFormat(_))));
+    }
+
+    #[test]
+    fn flattens_alpha_onto_background() {
+        let background = Color {
+            r: 0,
+            g: 64,
+            b: 255,
+        };
+        let bytes = [
+            200, 100, 0, 128, // half transparent
+            10, 20, 30, 255, // opaque
+            10, 20, 30, 0, // transparent
+        ];
+        assert_eq!(
+            flatten_alpha(&bytes, background),
+            [227, 82, 0, 10, 20, 30, 255, 64, 0]
+        );
+        assert_eq!("#0040ff".parse(), Ok(background));
+    }
+}
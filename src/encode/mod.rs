@@ -0,0 +1,297 @@
+mod exr;
+mod hdr;
+mod icc;
+mod jpeg;
+mod png;
+mod webp;
+
+use crate::frame::{Frame, PixelFormat};
+use crate::metadata::Metadata;
+use crate::template::Collision;
+use crate::{Result, ScreenshotError};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+pub use self::exr::{encode_exr, ExrCompression, ExrOptions};
+pub use self::hdr::encode_hdr;
+pub use self::icc::srgb_icc_profile;
+pub use self::jpeg::{encode_jpeg, flatten_alpha, ChromaSubsampling, Color, JpegOptions};
+pub use self::png::{
+    encode_png, ColorProfile, PngBitDepth, PngFilter, PngOptions, PngTransfer, LINEAR_PNG_MAX,
+};
+pub use self::webp::{encode_webp, WebPOptions};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jxr,
+    Jpeg,
+    WebP,
+    Exr,
+    Hdr,
+}
+
+impl ImageFormat {
+    /// The names accepted by [`ImageFormat::from_str`], which are also the file extensions.
+    pub const NAMES: &'static str = "png, jpg, webp, jxr, exr or hdr";
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        path.as_ref().extension()?.to_str()?.parse().ok()
+    }
+
+    /// The pixel format this format stores by default. See [`EncodeOptions::pixel_format`]
+    /// for formats with more than one.
+    pub fn pixel_format(&self) -> PixelFormat {
+        match self {
+            ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP => PixelFormat::Bgra8,
+            ImageFormat::Jxr | ImageFormat::Exr | ImageFormat::Hdr => PixelFormat::Rgba16Float,
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "png" => Ok(ImageFormat::Png),
+            "jxr" => Ok(ImageFormat::Jxr),
+            "jpg" | "jpeg" => Ok(ImageFormat::Jpeg),
+            "webp" => Ok(ImageFormat::WebP),
+            "exr" => Ok(ImageFormat::Exr),
+            "hdr" => Ok(ImageFormat::Hdr),
+            _ => Err(format!(
+                "'{}' isn't an image format, expecting {}",
+                input,
+                ImageFormat::NAMES
+            )),
+        }
+    }
+}
+
+/// Which implementation writes the image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Encoder {
+    /// The Windows Imaging Component. Only available on Windows.
+    Wic,
+    /// Encoders written in Rust. Available everywhere, but can't write JXR.
+    Native,
+}
+
+impl Encoder {
+    /// The encoder used for `format` when none was requested.
+    pub fn for_format(format: ImageFormat) -> Self {
+        match format {
+            ImageFormat::Png if cfg!(windows) => Encoder::Wic,
+            ImageFormat::Jxr => Encoder::Wic,
+            ImageFormat::Png
+            | ImageFormat::Jpeg
+            | ImageFormat::WebP
+            | ImageFormat::Exr
+            | ImageFormat::Hdr => Encoder::Native,
+        }
+    }
+}
+
+impl FromStr for Encoder {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "wic" => Ok(Encoder::Wic),
+            "native" => Ok(Encoder::Native),
+            _ => Err(format!(
+                "'{}' isn't an encoder, expecting wic or native",
+                input
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct EncodeOptions {
+    /// Picked with [`EncodeOptions::default_encoder`] if `None`.
+    pub encoder: Option<Encoder>,
+    pub png: PngOptions,
+    pub jpeg: JpegOptions,
+    pub webp: WebPOptions,
+    pub exr: ExrOptions,
+    /// Written by formats that support it. Currently only PNG does.
+    pub metadata: Option<Metadata>,
+}
+
+impl EncodeOptions {
+    /// The pixel format `format` is written from with these options. Captures in
+    /// another pixel format are converted before encoding.
+    pub fn pixel_format(&self, format: ImageFormat) -> PixelFormat {
+        match format {
+            ImageFormat::Png => self.png.bit_depth.pixel_format(),
+            format => format.pixel_format(),
+        }
+    }
+
+    /// Like [`Encoder::for_format`], but picks the native encoder for PNGs that
+    /// WIC can't write: 16-bit ones and ones with a compression level or filter.
+    pub fn default_encoder(&self, format: ImageFormat) -> Encoder {
+        match format {
+            ImageFormat::Png
+                if self.png.bit_depth == PngBitDepth::Sixteen || self.png_needs_native() =>
+            {
+                Encoder::Native
+            }
+            format => Encoder::for_format(format),
+        }
+    }
+
+    /// Whether the PNG compression level or filter differ from the defaults,
+    /// which WIC has no way to change.
+    fn png_needs_native(&self) -> bool {
+        let defaults = PngOptions::default();
+        self.png.compression_level != defaults.compression_level
+            || self.png.filter != defaults.filter
+    }
+}
+
+/// Encodes `frame` into a file at `path`, or wherever `collision` picks if it exists.
+pub fn save_frame(
+    frame: &Frame,
+    format: ImageFormat,
+    path: &str,
+    options: &EncodeOptions,
+    collision: Collision,
+) -> Result<()> {
+    // Fail before creating the file if the encoder can't write the format
+    pick_encoder(format, options)?;
+    let mut writer = BufWriter::new(collision.create_file(path)?);
+    encode_frame(frame, format, options, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Encodes `frame` as `format` into `writer`, which could be a file, a buffer or stdout.
+pub fn encode_frame<W: Write>(
+    frame: &Frame,
+    format: ImageFormat,
+    options: &EncodeOptions,
+    writer: W,
+) -> Result<()> {
+    match pick_encoder(format, options)? {
+        Encoder::Wic => encode_frame_wic(frame, format, options, writer),
+        Encoder::Native => encode_native(frame, format, options, writer),
+    }
+}
+
+fn pick_encoder(format: ImageFormat, options: &EncodeOptions) -> Result<Encoder> {
+    match options
+        .encoder
+        .unwrap_or_else(|| options.default_encoder(format))
+    {
+        Encoder::Native if format == ImageFormat::Jxr => Err(jxr_requires_wic()),
+        Encoder::Wic if format == ImageFormat::Png && options.png_needs_native() => {
+            Err(ScreenshotError::UnsupportedFormat(
+                "the WIC encoder can't change the PNG compression or filter, use --encoder native!"
+                    .to_owned(),
+            ))
+        }
+        Encoder::Wic if cfg!(not(windows)) => Err(wic_requires_windows(format)),
+        encoder => Ok(encoder),
+    }
+}
+
+fn encode_native<W: Write>(
+    frame: &Frame,
+    format: ImageFormat,
+    options: &EncodeOptions,
+    writer: W,
+) -> Result<()> {
+    match format {
+        ImageFormat::Png => encode_png(frame, &options.png, options.metadata.as_ref(), writer),
+        ImageFormat::Jpeg => encode_jpeg(frame, &options.jpeg, writer),
+        ImageFormat::WebP => encode_webp(frame, &options.webp, writer),
+        ImageFormat::Exr => encode_exr(frame, &options.exr, writer),
+        ImageFormat::Hdr => encode_hdr(frame, writer),
+        ImageFormat::Jxr => Err(jxr_requires_wic()),
+    }
+}
+
+fn bgra_to_rgba(bytes: &[u8]) -> Vec<u8> {
+    let mut rgba = bytes.to_vec();
+    for pixel in rgba.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+    rgba
+}
+
+fn jxr_requires_wic() -> ScreenshotError {
+    ScreenshotError::UnsupportedFormat("JXR can only be written by the WIC encoder!".to_owned())
+}
+
+fn wic_requires_windows(format: ImageFormat) -> ScreenshotError {
+    ScreenshotError::UnsupportedFormat(format!(
+        "encoding {:?} with WIC is only available on Windows!",
+        format
+    ))
+}
+
+#[cfg(windows)]
+fn encode_frame_wic<W: Write>(
+    frame: &Frame,
+    format: ImageFormat,
+    options: &EncodeOptions,
+    writer: W,
+) -> Result<()> {
+    let wic_factory = crate::wic::create_wic_factory()?;
+    crate::wic::encode_frame(&wic_factory, frame, format, options, writer)
+}
+
+#[cfg(not(windows))]
+fn encode_frame_wic<W: Write>(
+    _frame: &Frame,
+    format: ImageFormat,
+    _options: &EncodeOptions,
+    _writer: W,
+) -> Result<()> {
+    Err(wic_requires_windows(format))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::test_pattern;
+
+    #[test]
+    fn encodes_into_any_writer() {
+        let frame = test_pattern(4, 4, PixelFormat::Bgra8);
+        let options = EncodeOptions {
+            encoder: Some(Encoder::Native),
+            ..Default::default()
+        };
+        let mut bytes = Vec::new();
+        encode_frame(&frame, ImageFormat::Png, &options, &mut bytes).unwrap();
+        assert!(bytes.starts_with(b"\x89PNG"));
+
+        let error = encode_frame(&frame, ImageFormat::Jxr, &options, Vec::new()).unwrap_err();
+        assert_eq!(error.exit_code(), 6);
+    }
+
+    #[test]
+    fn picks_native_encoder_for_png_options() {
+        let mut options = EncodeOptions::default();
+        assert_eq!(
+            options.default_encoder(ImageFormat::Png),
+            Encoder::for_format(ImageFormat::Png)
+        );
+        options.png.compression_level = 9;
+        assert_eq!(options.default_encoder(ImageFormat::Png), Encoder::Native);
+        options.png.compression_level = 6;
+        options.png.filter = PngFilter::Paeth;
+        assert_eq!(options.default_encoder(ImageFormat::Png), Encoder::Native);
+        assert_eq!(options.default_encoder(ImageFormat::Jxr), Encoder::Wic);
+
+        options.encoder = Some(Encoder::Wic);
+        let frame = test_pattern(4, 4, PixelFormat::Bgra8);
+        let error = encode_frame(&frame, ImageFormat::Png, &options, Vec::new()).unwrap_err();
+        assert!(error.to_string().contains("--encoder native"), "{}", error);
+    }
+}
@@ -0,0 +1,588 @@
+use super::bgra_to_rgba;
+use super::icc::srgb_icc_profile;
+use crate::frame::{Frame, PixelFormat};
+use crate::metadata::Metadata;
+use crate::tonemap::{bt709_to_bt2020, pq_encode, SCRGB_WHITE_NITS};
+use crate::{Result, ScreenshotError};
+use std::io::Write;
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PngFilter {
+    None,
+    Sub,
+    Up,
+    Average,
+    Paeth,
+    /// Pick the best filter for each row.
+    Adaptive,
+}
+
+impl FromStr for PngFilter {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "none" => Ok(PngFilter::None),
+            "sub" => Ok(PngFilter::Sub),
+            "up" => Ok(PngFilter::Up),
+            "average" => Ok(PngFilter::Average),
+            "paeth" => Ok(PngFilter::Paeth),
+            "adaptive" => Ok(PngFilter::Adaptive),
+            _ => Err(format!(
+                "'{}' isn't a PNG filter, expecting none, sub, up, average, paeth or adaptive",
+                input
+            )),
+        }
+    }
+}
+
+/// How the color space of a PNG is signalled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColorProfile {
+    /// No color space information.
+    None,
+    /// The sRGB chunk for 8-bit images. 16-bit images get cICP, or gAMA and cHRM when linear.
+    Srgb,
+    /// An embedded sRGB ICC profile for 8-bit images. 16-bit images are signalled as for `Srgb`.
+    Icc,
+}
+
+impl FromStr for ColorProfile {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "none" => Ok(ColorProfile::None),
+            "srgb" => Ok(ColorProfile::Srgb),
+            "icc" => Ok(ColorProfile::Icc),
+            _ => Err(format!(
+                "'{}' isn't a color profile, expecting none, srgb or icc",
+                input
+            )),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PngBitDepth {
+    /// 8-bit sRGB, written from BGRA8 frames.
+    Eight,
+    /// 16-bit HDR, written from FP16 frames.
+    Sixteen,
+}
+
+impl PngBitDepth {
+    pub fn bits(&self) -> u8 {
+        match self {
+            PngBitDepth::Eight => 8,
+            PngBitDepth::Sixteen => 16,
+        }
+    }
+
+    /// The pixel format frames need to be in to be written at this depth.
+    pub fn pixel_format(&self) -> PixelFormat {
+        match self {
+            PngBitDepth::Eight => PixelFormat::Bgra8,
+            PngBitDepth::Sixteen => PixelFormat::Rgba16Float,
+        }
+    }
+}
+
+impl FromStr for PngBitDepth {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "8" => Ok(PngBitDepth::Eight),
+            "16" => Ok(PngBitDepth::Sixteen),
+            _ => Err(format!(
+                "'{}' isn't a PNG bit depth, expecting 8 or 16",
+                input
+            )),
+        }
+    }
+}
+
+/// How FP16 values are stored in a 16-bit PNG.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PngTransfer {
+    /// BT.2100 PQ with BT.2020 primaries, covering 0 to 10,000 nits.
+    Pq,
+    /// Linear scRGB with BT.709 primaries, divided by [`LINEAR_PNG_MAX`] so SDR white
+    /// (1.0) is stored as 524.
+    Linear,
+}
+
+/// The scRGB value stored as 65535 in linear 16-bit PNGs, 10,000 nits like PQ.
+pub const LINEAR_PNG_MAX: f32 = 125.0;
+
+/// The cICP chunk of PQ images: BT.2020 primaries, PQ, RGB and full range.
+const PQ_CODE_POINTS: [u8; 4] = [9, 16, 0, 1];
+
+impl FromStr for PngTransfer {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "pq" => Ok(PngTransfer::Pq),
+            "linear" => Ok(PngTransfer::Linear),
+            _ => Err(format!(
+                "'{}' isn't a PNG transfer function, expecting pq or linear",
+                input
+            )),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PngOptions {
+    /// Deflate level, from 0 (no compression) to 9 (smallest file).
+    pub compression_level: u8,
+    pub filter: PngFilter,
+    pub color_profile: ColorProfile,
+    pub bit_depth: PngBitDepth,
+    /// Only used by 16-bit images.
+    pub transfer: PngTransfer,
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        Self {
+            compression_level: 6,
+            filter: PngFilter::Adaptive,
+            color_profile: ColorProfile::Icc,
+            bit_depth: PngBitDepth::Eight,
+            transfer: PngTransfer::Pq,
+        }
+    }
+}
+
+/// BT.709 red, green and blue primaries and the D65 white point, in mDCV order.
+const BT709_CHROMATICITIES: [(f32, f32); 4] =
+    [(0.64, 0.33), (0.30, 0.60), (0.15, 0.06), (0.3127, 0.3290)];
+
+/// Encodes a frame as an RGBA PNG of `options.bit_depth`:
+///
+/// | Depth | Frame     | PNG                                                        |
+/// |-------|-----------|------------------------------------------------------------|
+/// | 8     | `bgra8`   | sRGB, with an sRGB or iCCP chunk                           |
+/// | 16    | `rgba16f` | PQ with cICP, mDCV and cLLI, or linear with gAMA and cHRM  |
+///
+/// `metadata` is written as text chunks, tEXt if it fits in Latin-1 and iTXt
+/// otherwise, plus a pHYs chunk if the DPI is known.
+pub fn encode_png<W: Write>(
+    frame: &Frame,
+    options: &PngOptions,
+    metadata: Option<&Metadata>,
+    writer: W,
+) -> Result<()> {
+    if options.compression_level > 9 {
+        return Err(ScreenshotError::Encode(format!(
+            "PNG compression level {} is out of range (0-9)!",
+            options.compression_level
+        )));
+    }
+
+    let mut info = png::Info::with_size(frame.width, frame.height);
+    info.color_type = png::ColorType::Rgba;
+    let (data, light_level) = match (options.bit_depth, frame.format) {
+        (PngBitDepth::Eight, PixelFormat::Bgra8) => {
+            info.bit_depth = png::BitDepth::Eight;
+            match options.color_profile {
+                ColorProfile::None => {}
+                ColorProfile::Srgb => info.srgb = Some(png::SrgbRenderingIntent::Perceptual),
+                ColorProfile::Icc => info.icc_profile = Some(srgb_icc_profile().into()),
+            }
+            (bgra_to_rgba(&frame.bytes), None)
+        }
+        (PngBitDepth::Sixteen, PixelFormat::Rgba16Float) => {
+            info.bit_depth = png::BitDepth::Sixteen;
+            let (data, light_level) = to_rgba16(frame, options.transfer);
+            match options.transfer {
+                _ if options.color_profile == ColorProfile::None => (data, None),
+                PngTransfer::Pq => (data, Some(light_level)),
+                PngTransfer::Linear => {
+                    info.source_gamma = Some(png::ScaledFloat::new(1.0));
+                    let [red, green, blue, white] = BT709_CHROMATICITIES;
+                    info.source_chromaticities =
+                        Some(png::SourceChromaticities::new(white, red, green, blue));
+                    (data, None)
+                }
+            }
+        }
+        (bit_depth, format) => {
+            return Err(ScreenshotError::UnsupportedFormat(format!(
+                "{}-bit PNG can't hold {:?} pixels!",
+                bit_depth.bits(),
+                format
+            )))
+        }
+    };
+
+    let mut encoder = png::Encoder::with_info(writer, info).map_err(map_png_error)?;
+    encoder.set_deflate_compression(match options.compression_level {
+        0 => png::DeflateCompression::NoCompression,
+        level => png::DeflateCompression::Level(level),
+    });
+    encoder.set_filter(match options.filter {
+        PngFilter::None => png::Filter::NoFilter,
+        PngFilter::Sub => png::Filter::Sub,
+        PngFilter::Up => png::Filter::Up,
+        PngFilter::Average => png::Filter::Avg,
+        PngFilter::Paeth => png::Filter::Paeth,
+        PngFilter::Adaptive => png::Filter::Adaptive,
+    });
+    if let Some(metadata) = metadata {
+        for (keyword, text) in metadata.text_entries() {
+            if text.chars().all(|c| (c as u32) < 0x100) {
+                encoder.add_text_chunk(keyword.to_owned(), text)
+            } else {
+                encoder.add_itxt_chunk(keyword.to_owned(), text)
+            }
+            .map_err(map_png_error)?;
+        }
+        encoder.set_pixel_dims(metadata.pixels_per_meter().map(|pixels_per_meter| {
+            png::PixelDimensions {
+                xppu: pixels_per_meter,
+                yppu: pixels_per_meter,
+                unit: png::Unit::Meter,
+            }
+        }));
+    }
+
+    let mut writer = encoder.write_header().map_err(map_png_error)?;
+    if let Some(light_level) = light_level {
+        write_hdr_chunks(&mut writer, &light_level).map_err(map_png_error)?;
+    }
+    writer.write_image_data(&data).map_err(map_png_error)?;
+    writer.finish().map_err(map_png_error)?;
+    Ok(())
+}
+
+/// The brightest pixel and the average pixel, in nits, measured on the
+/// largest of each pixel's color components as CTA-861.3 defines them.
+struct LightLevel {
+    max_content: f32,
+    max_frame_average: f32,
+}
+
+/// Converts scRGB to big-endian 16-bit samples with the given transfer function.
+fn to_rgba16(frame: &Frame, transfer: PngTransfer) -> (Vec<u8>, LightLevel) {
+    let mut data = Vec::with_capacity(frame.bytes.len());
+    let mut max_content = 0.0f32;
+    let mut total = 0.0f64;
+    for [r, g, b, a] in frame.rgba16f_pixels() {
+        // scRGB holds colors outside the sRGB gamut as negative components, so
+        // they're only clamped once they're in the target primaries
+        let nits = [r.to_f32(), g.to_f32(), b.to_f32()].map(|value| value * SCRGB_WHITE_NITS);
+        let brightest = nits[0].max(nits[1]).max(nits[2]).max(0.0);
+        max_content = max_content.max(brightest);
+        total += brightest as f64;
+        let [r, g, b] = match transfer {
+            PngTransfer::Pq => bt709_to_bt2020(nits).map(|nits| pq_encode(nits.max(0.0))),
+            PngTransfer::Linear => {
+                nits.map(|nits| (nits.max(0.0) / SCRGB_WHITE_NITS / LINEAR_PNG_MAX).min(1.0))
+            }
+        };
+        for value in [r, g, b, a.to_f32().clamp(0.0, 1.0)] {
+            data.extend_from_slice(&((value * 65535.0).round() as u16).to_be_bytes());
+        }
+    }
+    let pixel_count = (frame.width as u64 * frame.height as u64).max(1);
+    let light_level = LightLevel {
+        max_content,
+        max_frame_average: (total / pixel_count as f64) as f32,
+    };
+    (data, light_level)
+}
+
+/// Writes the cICP, mDCV and cLLI chunks of a PQ image. The desktop is composed in
+/// scRGB, so the mastering display is taken to have BT.709 primaries and to peak at
+/// the brightest pixel of the capture.
+fn write_hdr_chunks<W: Write>(
+    writer: &mut png::Writer<W>,
+    light_level: &LightLevel,
+) -> std::result::Result<(), png::EncodingError> {
+    // In units of 0.0001 nits
+    let luminance = |nits: f32| (nits as f64 * 10_000.0).round() as u32;
+
+    writer.write_chunk(png::chunk::cICP, &PQ_CODE_POINTS)?;
+
+    let mut mdcv = Vec::with_capacity(24);
+    for (x, y) in BT709_CHROMATICITIES {
+        for value in [x, y] {
+            mdcv.extend_from_slice(&((value * 50_000.0).round() as u16).to_be_bytes());
+        }
+    }
+    mdcv.extend_from_slice(&luminance(light_level.max_content.max(SCRGB_WHITE_NITS)).to_be_bytes());
+    mdcv.extend_from_slice(&0u32.to_be_bytes());
+    writer.write_chunk(png::chunk::mDCV, &mdcv)?;
+
+    let mut clli = Vec::with_capacity(8);
+    clli.extend_from_slice(&luminance(light_level.max_content).to_be_bytes());
+    clli.extend_from_slice(&luminance(light_level.max_frame_average).to_be_bytes());
+    writer.write_chunk(png::chunk::cLLI, &clli)
+}
+
+fn map_png_error(error: png::EncodingError) -> ScreenshotError {
+    match error {
+        png::EncodingError::IoError(error) => ScreenshotError::Io(error),
+        error => ScreenshotError::Encode(error.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::test_pattern;
+    use half::f16;
+    use std::io::Cursor;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn decode(bytes: &[u8]) -> (png::OutputInfo, Vec<u8>) {
+        let decoder = png::Decoder::new(Cursor::new(bytes));
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        pixels.truncate(info.buffer_size());
+        (info, pixels)
+    }
+
+    #[test]
+    fn round_trips_pixels() {
+        let frame = test_pattern(37, 19, PixelFormat::Bgra8);
+        let filters = [
+            PngFilter::None,
+            PngFilter::Sub,
+            PngFilter::Up,
+            PngFilter::Average,
+            PngFilter::Paeth,
+            PngFilter::Adaptive,
+        ];
+        for filter in filters {
+            for compression_level in [0, 1, 6, 9] {
+                let options = PngOptions {
+                    compression_level,
+                    filter,
+                    ..PngOptions::default()
+                };
+                let mut bytes = Vec::new();
+                encode_png(&frame, &options, None, &mut bytes).unwrap();
+
+                let (info, pixels) = decode(&bytes);
+                assert_eq!((info.width, info.height), (37, 19));
+                assert_eq!(info.color_type, png::ColorType::Rgba);
+                assert_eq!(pixels, bgra_to_rgba(&frame.bytes));
+            }
+        }
+    }
+
+    #[test]
+    fn writes_metadata() {
+        let frame = test_pattern(8, 8, PixelFormat::Bgra8);
+        let metadata = Metadata {
+            capture_time: UNIX_EPOCH + Duration::from_secs(1_714_570_620),
+            window_title: Some("Résumé — Editor".to_owned()),
+            window_class: Some("Notepad".to_owned()),
+            process_name: Some("notepad.exe".to_owned()),
+            monitor: Some(2),
+            software: "screenshot 1.2.3".to_owned(),
+            dpi: Some(144),
+        };
+        let mut bytes = Vec::new();
+        encode_png(&frame, &PngOptions::default(), Some(&metadata), &mut bytes).unwrap();
+
+        let reader = png::Decoder::new(Cursor::new(&bytes)).read_info().unwrap();
+        let info = reader.info();
+        let latin1: Vec<_> = info
+            .uncompressed_latin1_text
+            .iter()
+            .map(|chunk| (chunk.keyword.as_str(), chunk.text.as_str()))
+            .collect();
+        assert_eq!(
+            latin1,
+            [
+                ("Creation Time", "2024-05-01T13:37:00Z"),
+                ("Window Class", "Notepad"),
+                ("Monitor", "2"),
+                ("Software", "screenshot 1.2.3"),
+            ]
+        );
+        // The em dash doesn't fit in Latin-1
+        assert_eq!(info.utf8_text.len(), 1);
+        assert_eq!(info.utf8_text[0].keyword, "Title");
+        assert_eq!(info.utf8_text[0].get_text().unwrap(), "Résumé — Editor");
+        let dims = info.pixel_dims.unwrap();
+        assert_eq!(
+            (dims.xppu, dims.yppu, dims.unit),
+            (5669, 5669, png::Unit::Meter)
+        );
+    }
+
+    #[test]
+    fn omits_metadata() {
+        let frame = test_pattern(8, 8, PixelFormat::Bgra8);
+        let mut bytes = Vec::new();
+        encode_png(&frame, &PngOptions::default(), None, &mut bytes).unwrap();
+
+        let reader = png::Decoder::new(Cursor::new(&bytes)).read_info().unwrap();
+        let info = reader.info();
+        assert!(info.uncompressed_latin1_text.is_empty());
+        assert!(info.utf8_text.is_empty());
+        assert!(info.pixel_dims.is_none());
+    }
+
+    #[test]
+    fn signals_sdr_color_space() {
+        let frame = test_pattern(4, 4, PixelFormat::Bgra8);
+        let encode = |color_profile| {
+            let options = PngOptions {
+                color_profile,
+                ..PngOptions::default()
+            };
+            let mut bytes = Vec::new();
+            encode_png(&frame, &options, None, &mut bytes).unwrap();
+            bytes
+        };
+
+        let bytes = encode(ColorProfile::Icc);
+        let reader = png::Decoder::new(Cursor::new(&bytes)).read_info().unwrap();
+        let profile = reader.info().icc_profile.as_deref();
+        assert_eq!(profile, Some(&srgb_icc_profile()[..]));
+        assert!(reader.info().srgb.is_none());
+
+        let bytes = encode(ColorProfile::Srgb);
+        let reader = png::Decoder::new(Cursor::new(&bytes)).read_info().unwrap();
+        assert_eq!(
+            reader.info().srgb,
+            Some(png::SrgbRenderingIntent::Perceptual)
+        );
+        assert!(reader.info().icc_profile.is_none());
+
+        let bytes = encode(ColorProfile::None);
+        let reader = png::Decoder::new(Cursor::new(&bytes)).read_info().unwrap();
+        assert!(reader.info().srgb.is_none());
+        assert!(reader.info().icc_profile.is_none());
+    }
+
+    /// 80 nits white, 1000 nits white and transparent black.
+    fn hdr_frame() -> Frame {
+        let values: [[f32; 4]; 3] = [[1.0, 1.0, 1.0, 1.0], [12.5, 12.5, 12.5, 1.0], [0.0; 4]];
+        let mut bytes = Vec::new();
+        for value in values {
+            for channel in value {
+                bytes.extend_from_slice(&f16::from_f32(channel).to_le_bytes());
+            }
+        }
+        Frame::new(3, 1, PixelFormat::Rgba16Float, bytes)
+    }
+
+    fn samples(pixels: &[u8]) -> Vec<u16> {
+        pixels
+            .chunks_exact(2)
+            .map(|sample| u16::from_be_bytes([sample[0], sample[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn writes_fp16_frames_as_pq() {
+        let frame = hdr_frame();
+        let options = PngOptions {
+            bit_depth: PngBitDepth::Sixteen,
+            ..PngOptions::default()
+        };
+        let mut bytes = Vec::new();
+        encode_png(&frame, &options, None, &mut bytes).unwrap();
+
+        let (info, pixels) = decode(&bytes);
+        assert_eq!(info.bit_depth, png::BitDepth::Sixteen);
+        let samples = samples(&pixels);
+        let pq = |nits: f32| (pq_encode(nits) * 65535.0).round() as u16;
+        // White has the same coordinates in BT.709 and BT.2020
+        assert!(samples[..3]
+            .iter()
+            .all(|sample| sample.abs_diff(pq(80.0)) <= 2));
+        assert_eq!(samples[3], 65535);
+        assert!(samples[4..7]
+            .iter()
+            .all(|sample| sample.abs_diff(pq(1000.0)) <= 2));
+        assert_eq!(samples[8..], [0, 0, 0, 0]);
+
+        let reader = png::Decoder::new(Cursor::new(&bytes)).read_info().unwrap();
+        let info = reader.info();
+        let cicp = info.coding_independent_code_points.unwrap();
+        assert_eq!((cicp.color_primaries, cicp.transfer_function), (9, 16));
+        assert!(cicp.is_video_full_range_image);
+        let light_level = info.content_light_level.unwrap();
+        assert_eq!(light_level.max_content_light_level, 10_000_000);
+        assert_eq!(light_level.max_frame_average_light_level, 3_600_000);
+        let mastering = info.mastering_display_color_volume.unwrap();
+        assert_eq!(mastering.max_luminance, 10_000_000);
+    }
+
+    #[test]
+    fn keeps_wide_gamut_colors_in_pq() {
+        // A red outside sRGB, which BT.2020 can hold
+        let mut bytes = Vec::new();
+        for channel in [1.2, -0.04, -0.02, 1.0] {
+            bytes.extend_from_slice(&f16::from_f32(channel).to_le_bytes());
+        }
+        let frame = Frame::new(1, 1, PixelFormat::Rgba16Float, bytes);
+        let options = PngOptions {
+            bit_depth: PngBitDepth::Sixteen,
+            ..PngOptions::default()
+        };
+        let mut bytes = Vec::new();
+        encode_png(&frame, &options, None, &mut bytes).unwrap();
+
+        let (_, pixels) = decode(&bytes);
+        let pq = |scrgb: f32| (pq_encode(scrgb * SCRGB_WHITE_NITS) * 65535.0).round() as u16;
+        let samples = samples(&pixels);
+        assert!(samples[0].abs_diff(pq(0.739)) <= 16);
+        assert!(samples[1].abs_diff(pq(0.046)) <= 16);
+        assert_eq!(samples[2], pq(0.0));
+    }
+
+    #[test]
+    fn writes_fp16_frames_as_linear() {
+        let options = PngOptions {
+            bit_depth: PngBitDepth::Sixteen,
+            transfer: PngTransfer::Linear,
+            ..PngOptions::default()
+        };
+        let mut bytes = Vec::new();
+        encode_png(&hdr_frame(), &options, None, &mut bytes).unwrap();
+
+        let (_, pixels) = decode(&bytes);
+        // 65535 is scRGB 125.0
+        assert_eq!(
+            samples(&pixels),
+            [524, 524, 524, 65535, 6554, 6554, 6554, 65535, 0, 0, 0, 0]
+        );
+        let reader = png::Decoder::new(Cursor::new(&bytes)).read_info().unwrap();
+        let info = reader.info();
+        assert_eq!(info.gama_chunk, Some(png::ScaledFloat::new(1.0)));
+        let chromaticities = info.chrm_chunk.unwrap();
+        assert_eq!(chromaticities.red.0, png::ScaledFloat::new(0.64));
+        assert_eq!(chromaticities.white.1, png::ScaledFloat::new(0.3290));
+        assert!(info.coding_independent_code_points.is_none());
+        assert!(info.mastering_display_color_volume.is_none());
+        assert!(info.content_light_level.is_none());
+    }
+
+    #[test]
+    fn rejects_mismatched_bit_depth() {
+        let frame = test_pattern(4, 4, PixelFormat::Rgba16Float);
+        let result = encode_png(&frame, &PngOptions::default(), None, Vec::new());
+        assert!(matches!(result, Err(ScreenshotError::UnsupportedFormat(_))));
+
+        let frame = test_pattern(4, 4, PixelFormat::Bgra8);
+        let options = PngOptions {
+            bit_depth: PngBitDepth::Sixteen,
+            ..PngOptions::default()
+        };
+        let result = encode_png(&frame, &options, None, Vec::new());
+        assert!(matches!(result, Err(ScreenshotError::UnsupportedFormat(_))));
+    }
+}
@@ -0,0 +1,117 @@
+use super::bgra_to_rgba;
+use crate::frame::{Frame, PixelFormat};
+use crate::{Result, ScreenshotError};
+use std::io::Write;
+
+#[derive(Copy, Clone, Debug)]
+pub struct WebPOptions {
+    pub lossless: bool,
+    /// From 0 (smallest file) to 100 (best quality). Ignored when lossless.
+    pub quality: u8,
+}
+
+impl Default for WebPOptions {
+    fn default() -> Self {
+        Self {
+            lossless: false,
+            quality: 90,
+        }
+    }
+}
+
+/// Encodes a BGRA8 frame as a WebP image with an alpha channel.
+pub fn encode_webp<W: Write>(frame: &Frame, options: &WebPOptions, mut writer: W) -> Result<()> {
+    if frame.format != PixelFormat::Bgra8 {
+        return Err(ScreenshotError::UnsupportedFormat(format!(
+            "WebP can't hold {:?} pixels!",
+            frame.format
+        )));
+    }
+    if options.quality > 100 {
+        return Err(ScreenshotError::Encode(format!(
+            "WebP quality {} is out of range (0-100)!",
+            options.quality
+        )));
+    }
+
+    let mut config = webp::WebPConfig::new()
+        .map_err(|_| ScreenshotError::Encode("Failed to initialize libwebp!".to_owned()))?;
+    config.lossless = options.lossless as i32;
+    config.quality = options.quality as f32;
+    // Keep the color of fully transparent pixels, otherwise lossless isn't.
+    config.exact = options.lossless as i32;
+
+    let rgba = bgra_to_rgba(&frame.bytes);
+    let encoded = webp::Encoder::from_rgba(&rgba, frame.width, frame.height)
+        .encode_advanced(&config)
+        .map_err(|error| ScreenshotError::Encode(format!("{:?}", error)))?;
+    writer.write_all(&encoded)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::test_pattern;
+
+    /// The test pattern with a varying alpha channel.
+    fn translucent_pattern(width: u32, height: u32) -> Frame {
+        let mut frame = test_pattern(width, height, PixelFormat::Bgra8);
+        for (i, pixel) in frame.bytes.chunks_exact_mut(4).enumerate() {
+            pixel[3] = (i * 7) as u8;
+        }
+        frame
+    }
+
+    fn decode(bytes: &[u8]) -> (u32, u32, Vec<u8>) {
+        let image = webp::Decoder::new(bytes).decode().unwrap();
+        assert!(image.is_alpha());
+        (image.width(), image.height(), image.to_vec())
+    }
+
+    #[test]
+    fn lossless_round_trips_pixels() {
+        let frame = translucent_pattern(41, 23);
+        let mut bytes = Vec::new();
+        let options = WebPOptions {
+            lossless: true,
+            quality: 0,
+        };
+        encode_webp(&frame, &options, &mut bytes).unwrap();
+
+        let (width, height, pixels) = decode(&bytes);
+        assert_eq!((width, height), (41, 23));
+        assert_eq!(pixels, bgra_to_rgba(&frame.bytes));
+    }
+
+    #[test]
+    fn lossy_preserves_alpha() {
+        let (width, height) = (64, 32);
+        let mut bytes = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                bytes.extend_from_slice(&[(x * 4) as u8, (y * 8) as u8, 128, (x + y) as u8]);
+            }
+        }
+        let frame = Frame::new(width, height, PixelFormat::Bgra8, bytes);
+        let mut bytes = Vec::new();
+        let options = WebPOptions {
+            lossless: false,
+            quality: 90,
+        };
+        encode_webp(&frame, &options, &mut bytes).unwrap();
+
+        let (decoded_width, decoded_height, pixels) = decode(&bytes);
+        assert_eq!((decoded_width, decoded_height), (width, height));
+        let expected = bgra_to_rgba(&frame.bytes);
+        let mut color_error = 0;
+        for (actual, expected) in pixels.chunks_exact(4).zip(expected.chunks_exact(4)) {
+            assert_eq!(actual[3], expected[3]);
+            for channel in 0..3 {
+                color_error += (actual[channel] as i32 - expected[channel] as i32).unsigned_abs();
+            }
+        }
+        let mean_error = color_error as f64 / (width * height * 3) as f64;
+        assert!(mean_error < 4.0, "Mean error is {}", mean_error);
+    }
+}
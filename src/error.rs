@@ -0,0 +1,116 @@
+use crate::window_info::WindowInfo;
+use std::fmt;
+use std::time::Duration;
+
+pub type Result<T> = std::result::Result<T, ScreenshotError>;
+
+/// Everything that can go wrong while taking a screenshot. Each variant maps to
+/// a distinct process exit code, see [`ScreenshotError::exit_code`].
+#[derive(Debug)]
+pub enum ScreenshotError {
+    /// The requested window or monitor doesn't exist or can't be captured.
+    InvalidTarget(String),
+    /// No window title matched the query.
+    NoMatchingWindow(String),
+    /// More than one window matched the query and none was selected.
+    AmbiguousWindow {
+        query: String,
+        candidates: Vec<WindowInfo>,
+    },
+    /// The output format isn't supported, or can't hold the captured pixels.
+    UnsupportedFormat(String),
+    /// The capture didn't produce a frame in time.
+    CaptureTimeout(Duration),
+    /// The image couldn't be encoded.
+    Encode(String),
+    Io(std::io::Error),
+    /// An error reported by the operating system or graphics stack.
+    Platform(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl ScreenshotError {
+    /// The process exit code for this error:
+    ///
+    /// | Code | Error                |
+    /// |------|----------------------|
+    /// | 1    | `Platform`           |
+    /// | 2    | (command line usage) |
+    /// | 3    | `InvalidTarget`      |
+    /// | 4    | `NoMatchingWindow`   |
+    /// | 5    | `AmbiguousWindow`    |
+    /// | 6    | `UnsupportedFormat`  |
+    /// | 7    | `CaptureTimeout`     |
+    /// | 8    | `Encode`             |
+    /// | 9    | `Io`                 |
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ScreenshotError::Platform(_) => 1,
+            ScreenshotError::InvalidTarget(_) => 3,
+            ScreenshotError::NoMatchingWindow(_) => 4,
+            ScreenshotError::AmbiguousWindow { .. } => 5,
+            ScreenshotError::UnsupportedFormat(_) => 6,
+            ScreenshotError::CaptureTimeout(_) => 7,
+            ScreenshotError::Encode(_) => 8,
+            ScreenshotError::Io(_) => 9,
+        }
+    }
+
+    pub(crate) fn platform(message: &str) -> Self {
+        ScreenshotError::Platform(message.into())
+    }
+}
+
+impl fmt::Display for ScreenshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScreenshotError::InvalidTarget(message) => write!(f, "Invalid target: {}", message),
+            ScreenshotError::NoMatchingWindow(query) => {
+                write!(f, "No window matching '{}' found!", query)
+            }
+            ScreenshotError::AmbiguousWindow { query, candidates } => {
+                write!(
+                    f,
+                    "{} windows found matching '{}':\n    Num       PID    Window Title",
+                    candidates.len(),
+                    query
+                )?;
+                for (i, window) in candidates.iter().enumerate() {
+                    write!(f, "\n    {:>3}    {:>6}    {}", i, window.pid, window.title)?;
+                }
+                Ok(())
+            }
+            ScreenshotError::UnsupportedFormat(message) => {
+                write!(f, "Unsupported format: {}", message)
+            }
+            ScreenshotError::CaptureTimeout(timeout) => {
+                write!(f, "No frame was captured within {:?}!", timeout)
+            }
+            ScreenshotError::Encode(message) => write!(f, "Failed to encode image: {}", message),
+            ScreenshotError::Io(error) => error.fmt(f),
+            ScreenshotError::Platform(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ScreenshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ScreenshotError::Io(error) => Some(error),
+            ScreenshotError::Platform(error) => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ScreenshotError {
+    fn from(error: std::io::Error) -> Self {
+        ScreenshotError::Io(error)
+    }
+}
+
+#[cfg(windows)]
+impl From<windows::core::Error> for ScreenshotError {
+    fn from(error: windows::core::Error) -> Self {
+        ScreenshotError::Platform(Box::new(error))
+    }
+}
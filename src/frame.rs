@@ -0,0 +1,101 @@
+use half::f16;
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 8-bit BGRA, matches `DirectXPixelFormat::B8G8R8A8UIntNormalized`.
+    Bgra8,
+    /// 16-bit float RGBA (scRGB), matches `DirectXPixelFormat::R16G16B16A16Float`.
+    Rgba16Float,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(&self) -> u32 {
+        match self {
+            PixelFormat::Bgra8 => 4,
+            PixelFormat::Rgba16Float => 8,
+        }
+    }
+}
+
+impl FromStr for PixelFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "bgra8" => Ok(PixelFormat::Bgra8),
+            "rgba16f" => Ok(PixelFormat::Rgba16Float),
+            _ => Err(format!(
+                "'{}' isn't a pixel format, expecting bgra8 or rgba16f",
+                input
+            )),
+        }
+    }
+}
+
+/// A captured image that has been copied off the GPU. Rows are tightly packed.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    pub bytes: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(width: u32, height: u32, format: PixelFormat, bytes: Vec<u8>) -> Self {
+        assert_eq!(
+            Some(bytes.len()),
+            Self::byte_len(width, height, format),
+            "Frame size doesn't match the buffer!"
+        );
+        Self {
+            width,
+            height,
+            format,
+            bytes,
+        }
+    }
+
+    /// The size of the buffer of a `width` x `height` frame, or `None` if it
+    /// doesn't fit in memory.
+    pub fn byte_len(width: u32, height: u32, format: PixelFormat) -> Option<usize> {
+        (width as usize)
+            .checked_mul(height as usize)?
+            .checked_mul(format.bytes_per_pixel() as usize)
+    }
+
+    /// Copies a buffer whose rows are `row_pitch` bytes apart (e.g. a mapped
+    /// staging texture) into a tightly packed frame.
+    pub fn from_pitched(
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        row_pitch: u32,
+        slice: &[u8],
+    ) -> Self {
+        let stride = width as usize * format.bytes_per_pixel() as usize;
+        let mut bytes = vec![0u8; stride * height as usize];
+        for row in 0..height as usize {
+            let data_begin = row * stride;
+            let data_end = (row + 1) * stride;
+            let slice_begin = row * row_pitch as usize;
+            let slice_end = slice_begin + stride;
+            bytes[data_begin..data_end].copy_from_slice(&slice[slice_begin..slice_end]);
+        }
+        Self::new(width, height, format, bytes)
+    }
+
+    pub fn stride(&self) -> u32 {
+        self.width * self.format.bytes_per_pixel()
+    }
+
+    /// Reads the pixels of a [`PixelFormat::Rgba16Float`] frame as `[r, g, b, a]` halves.
+    pub fn rgba16f_pixels(&self) -> impl Iterator<Item = [f16; 4]> + '_ {
+        assert_eq!(self.format, PixelFormat::Rgba16Float);
+        self.bytes.chunks_exact(8).map(|pixel| {
+            let channel = |i: usize| f16::from_le_bytes([pixel[i * 2], pixel[i * 2 + 1]]);
+            [channel(0), channel(1), channel(2), channel(3)]
+        })
+    }
+}
@@ -0,0 +1,984 @@
+//! Take screenshots of windows or monitors using `Windows.Graphics.Capture`.
+//!
+//! The capture itself is only available on Windows, but everything that happens
+//! to a [`Frame`] after it has been copied off the GPU is platform-neutral.
+
+mod backend;
+#[cfg(windows)]
+mod capture;
+mod convert;
+#[cfg(windows)]
+mod d3d;
+mod display_info;
+mod encode;
+mod error;
+mod frame;
+mod matcher;
+mod metadata;
+#[cfg(any(test, feature = "mock"))]
+mod mock;
+mod region;
+mod schedule;
+mod template;
+mod tonemap;
+#[cfg(windows)]
+mod wic;
+mod window_info;
+
+pub use backend::{
+    default_backend, receive_frame, CaptureBackend, CaptureSession, CaptureSource,
+    DEFAULT_CAPTURE_TIMEOUT,
+};
+pub use convert::convert_frame;
+pub use display_info::{AdvancedColor, DisplayInfo};
+pub use encode::{
+    encode_exr, encode_frame, encode_hdr, encode_jpeg, encode_png, encode_webp, flatten_alpha,
+    save_frame, srgb_icc_profile, ChromaSubsampling, Color, ColorProfile, EncodeOptions, Encoder,
+    ExrCompression, ExrOptions, ImageFormat, JpegOptions, PngBitDepth, PngFilter, PngOptions,
+    PngTransfer, WebPOptions, LINEAR_PNG_MAX,
+};
+pub use error::{Result, ScreenshotError};
+pub use frame::{Frame, PixelFormat};
+pub use matcher::{MatchMode, TitleMatcher};
+pub use metadata::{filename_timestamp, format_timestamp, Metadata};
+#[cfg(any(test, feature = "mock"))]
+pub use mock::{test_pattern, test_pattern_pixel, MockBackend, MockClock};
+pub use region::{bounding_rect, compose_frames, crop_frame, Rect};
+pub use schedule::{Clock, Schedule, SystemClock};
+pub use template::{sanitize_file_name, Collision, FilenameTemplate};
+pub use tonemap::{
+    bt709_to_bt2020, linear_to_srgb, pq_decode, pq_encode, srgb_to_linear, tone_map,
+    ToneMapOperator, ToneMapOptions, SCRGB_WHITE_NITS,
+};
+pub use window_info::{find_windows, WindowFilter, WindowInfo, WindowSelection};
+
+#[cfg(windows)]
+pub use capture::{enumerate_capturable_windows, WgcBackend};
+#[cfg(windows)]
+pub use display_info::enumerate_displays;
+
+use std::time::{Duration, Instant, SystemTime};
+
+/// What to capture.
+#[derive(Clone, Debug)]
+pub enum CaptureTarget {
+    /// A window who's title contains the provided query.
+    Window(String),
+    /// A monitor by its 1-based index.
+    Monitor(usize),
+    /// The primary monitor.
+    Primary,
+    /// A rectangle of the virtual desktop, which may span several monitors. Parts
+    /// outside the virtual desktop are left out.
+    Region(Rect),
+    /// Every monitor, stitched into one image of the virtual desktop.
+    AllMonitors,
+    /// The window the user is working in.
+    Foreground,
+    /// The window under the mouse cursor.
+    UnderCursor,
+}
+
+/// Picks one of several windows that matched a query. Returning `None` aborts the capture.
+pub type WindowSelector = Box<dyn Fn(&str, &[WindowInfo]) -> Option<usize>>;
+
+/// Told how much of the delay is left, about once a second.
+pub type Countdown = Box<dyn Fn(Duration)>;
+
+/// How often [`Screenshot::wait_for_window`] looks for the window.
+const WINDOW_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct Screenshot {
+    target: CaptureTarget,
+    pixel_format: PixelFormat,
+    window_selector: Option<WindowSelector>,
+    window_selection: Option<WindowSelection>,
+    match_mode: MatchMode,
+    case_sensitive: bool,
+    window_filter: WindowFilter,
+    backend: Option<Box<dyn CaptureBackend>>,
+    crop: Option<Rect>,
+    timeout: Duration,
+    delay: Option<Duration>,
+    countdown: Option<Countdown>,
+    window_wait: Option<Duration>,
+}
+
+impl Screenshot {
+    pub fn new(target: CaptureTarget) -> Self {
+        Self {
+            target,
+            pixel_format: PixelFormat::Bgra8,
+            window_selector: None,
+            window_selection: None,
+            match_mode: MatchMode::Substring,
+            case_sensitive: false,
+            window_filter: WindowFilter::default(),
+            backend: default_backend(),
+            crop: None,
+            timeout: DEFAULT_CAPTURE_TIMEOUT,
+            delay: None,
+            countdown: None,
+            window_wait: None,
+        }
+    }
+
+    pub fn pixel_format(mut self, pixel_format: PixelFormat) -> Self {
+        self.pixel_format = pixel_format;
+        self
+    }
+
+    /// Called when more than one window matches a [`CaptureTarget::Window`] query.
+    pub fn window_selector<F>(mut self, selector: F) -> Self
+    where
+        F: Fn(&str, &[WindowInfo]) -> Option<usize> + 'static,
+    {
+        self.window_selector = Some(Box::new(selector));
+        self
+    }
+
+    /// Picks one of several matching windows by `selection`, instead of asking
+    /// the window selector.
+    pub fn window_selection(mut self, selection: WindowSelection) -> Self {
+        self.window_selection = Some(selection);
+        self
+    }
+
+    /// How a [`CaptureTarget::Window`] query is compared against window titles.
+    pub fn match_mode(mut self, match_mode: MatchMode) -> Self {
+        self.match_mode = match_mode;
+        self
+    }
+
+    /// Compare window titles case sensitively. Titles are compared ignoring
+    /// case by default.
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.case_sensitive = case_sensitive;
+        self
+    }
+
+    /// Only considers windows passing `filter` for a [`CaptureTarget::Window`]
+    /// query. An empty query then matches any window passing the filter.
+    pub fn window_filter(mut self, filter: WindowFilter) -> Self {
+        self.window_filter = filter;
+        self
+    }
+
+    pub fn backend<B: CaptureBackend + 'static>(mut self, backend: B) -> Self {
+        self.backend = Some(Box::new(backend));
+        self
+    }
+
+    /// Crops the captured frame to `rect`, relative to its top left corner.
+    pub fn crop(mut self, rect: Rect) -> Self {
+        self.crop = Some(rect);
+        self
+    }
+
+    /// How long to wait for each frame before failing with
+    /// [`ScreenshotError::CaptureTimeout`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Waits `delay` after finding the target and before capturing it.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Called with the time left about once a second while waiting for the delay.
+    pub fn countdown<F>(mut self, countdown: F) -> Self
+    where
+        F: Fn(Duration) + 'static,
+    {
+        self.countdown = Some(Box::new(countdown));
+        self
+    }
+
+    /// Keeps looking for a window matching a [`CaptureTarget::Window`] query for
+    /// up to `timeout`, instead of failing when there's none yet.
+    pub fn wait_for_window(mut self, timeout: Duration) -> Self {
+        self.window_wait = Some(timeout);
+        self
+    }
+
+    pub fn target(&self) -> &CaptureTarget {
+        &self.target
+    }
+
+    /// Resolves a window query against a list of candidates.
+    pub fn select_window(&self, query: &str, windows: &[WindowInfo]) -> Result<WindowInfo> {
+        let windows = self.matching_windows(query, windows)?;
+        // How errors and the selector refer to the query
+        let query = match (query.is_empty(), self.window_filter.is_empty()) {
+            (_, true) => query.to_owned(),
+            (true, false) => self.window_filter.to_string(),
+            (false, false) => format!("{}, {}", query, self.window_filter),
+        };
+        let query = query.as_str();
+        match windows.len() {
+            0 => Err(ScreenshotError::NoMatchingWindow(query.to_owned())),
+            1 => Ok(windows[0].clone()),
+            _ => {
+                let selection = match self.window_selection {
+                    Some(selection) => {
+                        let foreground = match (selection, self.backend.as_deref()) {
+                            (WindowSelection::Foreground, Some(backend)) => {
+                                backend.foreground_window()?.map(|window| window.handle)
+                            }
+                            _ => None,
+                        };
+                        let index = selection.select(&windows, foreground).ok_or_else(|| {
+                            ScreenshotError::InvalidTarget(format!(
+                                "'{}' doesn't pick any of the {} windows matching '{}'!",
+                                selection,
+                                windows.len(),
+                                query
+                            ))
+                        })?;
+                        Some(index)
+                    }
+                    None => self
+                        .window_selector
+                        .as_ref()
+                        .and_then(|selector| selector(query, &windows)),
+                };
+                match selection.and_then(|index| windows.get(index)) {
+                    Some(window) => Ok(window.clone()),
+                    None => Err(ScreenshotError::AmbiguousWindow {
+                        query: query.to_owned(),
+                        candidates: windows,
+                    }),
+                }
+            }
+        }
+    }
+
+    fn matching_windows(&self, query: &str, windows: &[WindowInfo]) -> Result<Vec<WindowInfo>> {
+        let windows: Vec<WindowInfo> = windows
+            .iter()
+            .filter(|window| self.window_filter.matches(window))
+            .cloned()
+            .collect();
+        TitleMatcher::new(query, self.match_mode, self.case_sensitive)
+            .map(|matcher| matcher.filter(&windows))
+    }
+
+    /// Enumerates windows until one matches `query` or the wait times out.
+    fn poll_windows(&self, backend: &dyn CaptureBackend, query: &str) -> Result<Vec<WindowInfo>> {
+        let start = Instant::now();
+        loop {
+            let windows = backend.enumerate_windows()?;
+            let remaining = self
+                .window_wait
+                .unwrap_or_default()
+                .saturating_sub(start.elapsed());
+            if remaining.is_zero() || !self.matching_windows(query, &windows)?.is_empty() {
+                return Ok(windows);
+            }
+            std::thread::sleep(remaining.min(WINDOW_POLL_INTERVAL));
+        }
+    }
+
+    /// Sleeps for the delay, if there is one, ticking the countdown on whole seconds.
+    fn wait_for_delay(&self, metadata: &mut Metadata) {
+        let Some(delay) = self.delay else {
+            return;
+        };
+        let start = Instant::now();
+        loop {
+            let remaining = delay.saturating_sub(start.elapsed());
+            if remaining.is_zero() {
+                break;
+            }
+            if let Some(countdown) = &self.countdown {
+                countdown(remaining);
+            }
+            let fraction = Duration::from_nanos(remaining.subsec_nanos() as u64);
+            std::thread::sleep(if fraction.is_zero() {
+                Duration::from_secs(1)
+            } else {
+                fraction
+            });
+        }
+        metadata.capture_time = SystemTime::now();
+    }
+
+    pub fn capture(&self) -> Result<Frame> {
+        self.capture_with_metadata().map(|(frame, _)| frame)
+    }
+
+    /// Captures a frame along with a description of where and when it was taken.
+    pub fn capture_with_metadata(&self) -> Result<(Frame, Metadata)> {
+        let backend = self.require_backend()?;
+        let plan = self.plan(backend)?;
+        let frame = self.capture_plan(&plan, |_, source| {
+            backend.capture(source, self.pixel_format, self.timeout)
+        })?;
+        Ok((frame, plan.metadata))
+    }
+
+    /// Captures frames as laid out by `schedule`, handing each to `on_frame` with
+    /// its 0-based index. The target is looked up once and kept capturing between
+    /// frames. Returns how many frames were taken.
+    pub fn capture_series<F>(
+        &self,
+        schedule: &Schedule,
+        clock: &dyn Clock,
+        mut on_frame: F,
+    ) -> Result<usize>
+    where
+        F: FnMut(usize, Frame, Metadata) -> Result<()>,
+    {
+        let backend = self.require_backend()?;
+        let plan = self.plan(backend)?;
+        let mut sessions = plan
+            .sources()
+            .iter()
+            .map(|source| backend.start_session(source, self.pixel_format))
+            .collect::<Result<Vec<_>>>()?;
+        let start = clock.now();
+        let mut index = 0;
+        while let Some(due) = schedule.due(index) {
+            let elapsed = clock.now().duration_since(start).unwrap_or_default();
+            // Slow frames can run past the end of the series
+            if index > 0 && schedule.until.is_some_and(|until| elapsed >= until) {
+                break;
+            }
+            if due > elapsed {
+                clock.sleep(due - elapsed);
+            }
+            let mut metadata = plan.metadata.clone();
+            metadata.capture_time = clock.now();
+            let frame = self.capture_plan(&plan, |source_index, _| {
+                sessions[source_index].next_frame(self.timeout)
+            })?;
+            on_frame(index, frame, metadata)?;
+            index += 1;
+        }
+        Ok(index)
+    }
+
+    fn require_backend(&self) -> Result<&dyn CaptureBackend> {
+        self.backend.as_deref().ok_or_else(|| {
+            ScreenshotError::platform("Windows.Graphics.Capture is only available on Windows!")
+        })
+    }
+
+    /// Finds what to capture, waiting for the delay along the way.
+    fn plan(&self, backend: &dyn CaptureBackend) -> Result<Plan> {
+        let mut metadata = Metadata::now();
+        // Give time to bring up the window before looking for it
+        let delay_first = matches!(
+            self.target,
+            CaptureTarget::Foreground | CaptureTarget::UnderCursor
+        );
+        if delay_first {
+            self.wait_for_delay(&mut metadata);
+        }
+        let source = match &self.target {
+            CaptureTarget::Window(query) => CaptureSource::Window(
+                self.select_window(query, &self.poll_windows(backend, query)?)?,
+            ),
+            CaptureTarget::Monitor(id) => {
+                let displays = backend.enumerate_displays()?;
+                if *id == 0 {
+                    return Err(ScreenshotError::InvalidTarget(
+                        "monitor ids start with 1.".to_owned(),
+                    ));
+                }
+                let index = id - 1;
+                if index >= displays.len() {
+                    return Err(ScreenshotError::InvalidTarget(format!(
+                        "monitor id {} is higher than the number of displays ({})!",
+                        id,
+                        displays.len()
+                    )));
+                }
+                metadata.monitor = Some(*id);
+                CaptureSource::Monitor(displays[index].clone())
+            }
+            CaptureTarget::Primary => {
+                let displays = backend.enumerate_displays()?;
+                metadata.monitor = displays
+                    .iter()
+                    .position(|display| display.is_primary)
+                    .map(|index| index + 1);
+                CaptureSource::Primary
+            }
+            CaptureTarget::Foreground => CaptureSource::Window(capturable_window(
+                backend,
+                backend.foreground_window()?,
+                "in the foreground",
+            )?),
+            CaptureTarget::UnderCursor => CaptureSource::Window(capturable_window(
+                backend,
+                backend.window_under_cursor()?,
+                "under the cursor",
+            )?),
+            CaptureTarget::Region(region) => {
+                return self.plan_region(backend, Some(region), metadata);
+            }
+            CaptureTarget::AllMonitors => return self.plan_region(backend, None, metadata),
+        };
+        if let CaptureSource::Window(window) = &source {
+            metadata.window_title = Some(window.title.clone());
+            metadata.window_class = Some(window.class_name.clone());
+            metadata.process_name = window.process_name.clone();
+        }
+        metadata.dpi = backend.dpi(&source)?;
+        if !delay_first {
+            self.wait_for_delay(&mut metadata);
+        }
+        Ok(Plan {
+            sources: Sources::Single(source),
+            metadata,
+        })
+    }
+
+    /// Plans to capture every monitor overlapping `region` and compose them into
+    /// one frame. Without a region, the whole virtual desktop is captured.
+    fn plan_region(
+        &self,
+        backend: &dyn CaptureBackend,
+        region: Option<&Rect>,
+        mut metadata: Metadata,
+    ) -> Result<Plan> {
+        let displays = backend.enumerate_displays()?;
+        let display_rects: Vec<Rect> = displays.iter().map(|display| display.rect).collect();
+        let desktop = bounding_rect(&display_rects).ok_or_else(|| {
+            ScreenshotError::InvalidTarget("there are no monitors to capture!".to_owned())
+        })?;
+        // Nothing outside the desktop can be captured, so don't make room for it
+        let region = match region {
+            Some(region) => region.intersection(&desktop).ok_or_else(|| {
+                ScreenshotError::InvalidTarget(format!(
+                    "the region {},{},{},{} doesn't overlap any monitor!",
+                    region.x, region.y, region.width, region.height
+                ))
+            })?,
+            None => desktop,
+        };
+        let covering: Vec<(usize, &DisplayInfo)> = displays
+            .iter()
+            .enumerate()
+            .filter(|(_, display)| display.rect.intersection(&region).is_some())
+            .collect();
+        let Some((first_index, first_display)) = covering.first() else {
+            return Err(ScreenshotError::InvalidTarget(format!(
+                "the region {},{},{},{} doesn't overlap any monitor!",
+                region.x, region.y, region.width, region.height
+            )));
+        };
+        if covering.len() == 1 {
+            metadata.monitor = Some(first_index + 1);
+        }
+        metadata.dpi = backend.dpi(&CaptureSource::Monitor((*first_display).clone()))?;
+
+        self.wait_for_delay(&mut metadata);
+        Ok(Plan {
+            sources: Sources::Region {
+                region,
+                displays: covering
+                    .into_iter()
+                    .map(|(_, display)| display.clone())
+                    .collect(),
+            },
+            metadata,
+        })
+    }
+
+    /// Captures every source of `plan` with `capture`, which is also given the
+    /// index of the source, and puts the frames together.
+    fn capture_plan<F>(&self, plan: &Plan, mut capture: F) -> Result<Frame>
+    where
+        F: FnMut(usize, &CaptureSource) -> Result<Frame>,
+    {
+        let frame = match &plan.sources {
+            Sources::Single(source) => capture(0, source)?,
+            Sources::Region { region, displays } => {
+                let mut frames = Vec::with_capacity(displays.len());
+                for (index, display) in displays.iter().enumerate() {
+                    let source = CaptureSource::Monitor(display.clone());
+                    frames.push((display.rect, capture(index, &source)?));
+                }
+                compose_frames(&frames, region)?
+            }
+        };
+        self.crop_frame(frame)
+    }
+
+    fn crop_frame(&self, frame: Frame) -> Result<Frame> {
+        match &self.crop {
+            Some(rect) => crop_frame(&frame, rect),
+            None => Ok(frame),
+        }
+    }
+}
+
+/// A capture target that has been looked up.
+struct Plan {
+    sources: Sources,
+    metadata: Metadata,
+}
+
+enum Sources {
+    Single(CaptureSource),
+    /// Monitors composed into one frame of a region of the virtual desktop.
+    Region {
+        region: Rect,
+        displays: Vec<DisplayInfo>,
+    },
+}
+
+impl Plan {
+    fn sources(&self) -> Vec<CaptureSource> {
+        match &self.sources {
+            Sources::Single(source) => vec![source.clone()],
+            Sources::Region { displays, .. } => displays
+                .iter()
+                .map(|display| CaptureSource::Monitor(display.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// Checks that a window found by position or focus is one that could have been
+/// picked by title, so the desktop, taskbar or tooltips aren't captured.
+fn capturable_window(
+    backend: &dyn CaptureBackend,
+    window: Option<WindowInfo>,
+    description: &str,
+) -> Result<WindowInfo> {
+    let window = window.ok_or_else(|| {
+        ScreenshotError::InvalidTarget(format!("there is no window {}!", description))
+    })?;
+    let capturable = backend
+        .enumerate_windows()?
+        .iter()
+        .any(|candidate| candidate.handle == window.handle);
+    if !capturable {
+        return Err(ScreenshotError::InvalidTarget(format!(
+            "the window {} ('{}', {}) can't be captured!",
+            description, window.title, window.class_name
+        )));
+    }
+    Ok(window)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::{Instant, UNIX_EPOCH};
+
+    fn backend() -> MockBackend {
+        MockBackend::new()
+            .with_window("Untitled - Notepad", "Notepad", 64, 32)
+            .with_window("Notepad++", "Notepad++", 48, 48)
+            .with_window("Calculator", "ApplicationFrameWindow", 20, 30)
+            .with_display(128, 72)
+            .with_display(96, 54)
+    }
+
+    #[test]
+    fn captures_unique_window() {
+        let frame = Screenshot::new(CaptureTarget::Window("calc".to_owned()))
+            .backend(backend())
+            .capture()
+            .unwrap();
+        assert_eq!((frame.width, frame.height), (20, 30));
+        assert_eq!(frame.bytes[..4], test_pattern_pixel(0, 0));
+    }
+
+    #[test]
+    fn ambiguous_window_uses_selector() {
+        let frame = Screenshot::new(CaptureTarget::Window("notepad".to_owned()))
+            .backend(backend())
+            .window_selector(|_, windows| windows.iter().position(|w| w.title == "Notepad++"))
+            .capture()
+            .unwrap();
+        assert_eq!((frame.width, frame.height), (48, 48));
+
+        let result = Screenshot::new(CaptureTarget::Window("notepad".to_owned()))
+            .backend(backend())
+            .capture();
+        match result {
+            Err(error @ ScreenshotError::AmbiguousWindow { .. }) => {
+                // The candidates are listed for whoever has to pick one
+                let message = error.to_string();
+                assert!(message.contains("Untitled - Notepad") && message.contains("Notepad++"));
+            }
+            _ => panic!("Expected an ambiguous window error!"),
+        }
+    }
+
+    #[test]
+    fn selects_window_without_asking() {
+        let size = |selection: &str| {
+            Screenshot::new(CaptureTarget::Window("notepad".to_owned()))
+                .backend(backend().with_foreground("Untitled - Notepad"))
+                .window_selection(selection.parse().unwrap())
+                .capture()
+                .map(|frame| (frame.width, frame.height))
+        };
+        assert_eq!(size("first").unwrap(), (64, 32));
+        assert_eq!(size("last").unwrap(), (48, 48));
+        assert_eq!(size("largest").unwrap(), (48, 48));
+        assert_eq!(size("foreground").unwrap(), (64, 32));
+        assert_eq!(size("index:1").unwrap(), (48, 48));
+        assert_eq!(size("index:2").unwrap_err().exit_code(), 3);
+        assert!("index:x".parse::<crate::WindowSelection>().is_err());
+    }
+
+    #[test]
+    fn filters_windows_by_owner_and_class() {
+        let backend = || {
+            backend()
+                .with_window("Report.docx - Word", "OpusApp", 80, 60)
+                .with_process(42, "WINWORD.EXE")
+                .with_window("Notes.docx - Word", "OpusApp", 40, 30)
+                .with_process(42, "WINWORD.EXE")
+        };
+        let capture = |query: &str, filter: WindowFilter| {
+            Screenshot::new(CaptureTarget::Window(query.to_owned()))
+                .backend(backend())
+                .window_filter(filter)
+                .capture()
+                .map(|frame| (frame.width, frame.height))
+        };
+        let process = |name: &str| WindowFilter {
+            process_name: Some(name.to_owned()),
+            ..Default::default()
+        };
+
+        assert_eq!(capture("notes", process("winword")).unwrap(), (40, 30));
+        assert_eq!(
+            capture("", process("notepad.exe")).unwrap_err().exit_code(),
+            4
+        );
+        match capture("", process("winword.exe")) {
+            Err(ScreenshotError::AmbiguousWindow { query, .. }) => {
+                assert_eq!(query, "process winword.exe")
+            }
+            _ => panic!("Expected an ambiguous window error!"),
+        }
+
+        let class = WindowFilter {
+            class_name: Some("applicationframewindow".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(capture("", class).unwrap(), (20, 30));
+        let handle = WindowFilter {
+            handle: Some(0x10010),
+            pid: Some(1001),
+            ..Default::default()
+        };
+        assert_eq!(capture("", handle).unwrap(), (48, 48));
+    }
+
+    #[test]
+    fn captures_foreground_and_cursor_windows() {
+        let backend = || {
+            backend()
+                .with_foreground("Calculator")
+                .with_cursor_over("Notepad++")
+        };
+        let (frame, metadata) = Screenshot::new(CaptureTarget::Foreground)
+            .backend(backend())
+            .capture_with_metadata()
+            .unwrap();
+        assert_eq!((frame.width, frame.height), (20, 30));
+        assert_eq!(metadata.window_title.as_deref(), Some("Calculator"));
+
+        let frame = Screenshot::new(CaptureTarget::UnderCursor)
+            .backend(backend())
+            .capture()
+            .unwrap();
+        assert_eq!((frame.width, frame.height), (48, 48));
+
+        let error = Screenshot::new(CaptureTarget::Foreground)
+            .backend(MockBackend::new())
+            .capture()
+            .unwrap_err();
+        assert_eq!(error.exit_code(), 3);
+    }
+
+    #[test]
+    fn times_out_without_frames() {
+        let timeout = Duration::from_millis(50);
+        for target in [CaptureTarget::Primary, CaptureTarget::AllMonitors] {
+            let start = Instant::now();
+            let error = Screenshot::new(target)
+                .backend(backend().stalled())
+                .timeout(timeout)
+                .capture()
+                .unwrap_err();
+            assert!(start.elapsed() >= timeout);
+            assert!(matches!(error, ScreenshotError::CaptureTimeout(t) if t == timeout));
+            assert_eq!(error.exit_code(), 7);
+        }
+    }
+
+    #[test]
+    fn waits_for_slow_frames() {
+        let capture = |delay, timeout| {
+            Screenshot::new(CaptureTarget::Primary)
+                .backend(backend().with_frame_delay(Duration::from_millis(delay)))
+                .timeout(Duration::from_millis(timeout))
+                .capture()
+        };
+        assert_eq!(capture(10, 5000).unwrap().width, 128);
+        assert_eq!(capture(5000, 10).unwrap_err().exit_code(), 7);
+    }
+
+    #[test]
+    fn waits_for_window_to_appear() {
+        let backend = || backend().with_late_window("Installer", "#32770", 30, 20, 2);
+        let capture = |wait: Option<Duration>| {
+            let mut screenshot =
+                Screenshot::new(CaptureTarget::Window("installer".to_owned())).backend(backend());
+            if let Some(wait) = wait {
+                screenshot = screenshot.wait_for_window(wait);
+            }
+            screenshot.capture()
+        };
+        assert_eq!(capture(None).unwrap_err().exit_code(), 4);
+        assert_eq!(capture(Some(Duration::from_secs(5))).unwrap().width, 30);
+
+        let start = Instant::now();
+        let error = capture(Some(Duration::from_millis(50))).unwrap_err();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(error.exit_code(), 4);
+    }
+
+    #[test]
+    fn counts_down_delay() {
+        let ticks = Rc::new(Cell::new(0));
+        let start = Instant::now();
+        let frame = Screenshot::new(CaptureTarget::Primary)
+            .backend(backend())
+            .delay(Duration::from_millis(30))
+            .countdown({
+                let ticks = ticks.clone();
+                move |remaining| {
+                    assert!(remaining <= Duration::from_millis(30));
+                    ticks.set(ticks.get() + 1);
+                }
+            })
+            .capture()
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert_eq!(frame.width, 128);
+        // A delay under a second ticks once
+        assert_eq!(ticks.get(), 1);
+    }
+
+    #[test]
+    fn captures_series_from_one_session() {
+        let backend = backend();
+        let clock = MockClock::new(UNIX_EPOCH);
+        let schedule = Schedule {
+            count: Some(3),
+            interval: Duration::from_millis(500),
+            until: None,
+        };
+        let mut times = Vec::new();
+        let taken = Screenshot::new(CaptureTarget::AllMonitors)
+            .backend(backend.clone())
+            .capture_series(&schedule, &clock, |index, frame, metadata| {
+                assert_eq!(index, times.len());
+                assert_eq!((frame.width, frame.height), (224, 72));
+                times.push(metadata.capture_time.duration_since(UNIX_EPOCH).unwrap());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(taken, 3);
+        assert_eq!(times, [0, 500, 1000].map(Duration::from_millis));
+        // One session per monitor, kept for the whole series
+        assert_eq!(backend.sessions_started(), 2);
+    }
+
+    #[test]
+    fn series_catches_up_after_slow_frames() {
+        let clock = MockClock::new(UNIX_EPOCH);
+        let schedule = Schedule {
+            count: None,
+            interval: Duration::from_millis(500),
+            until: Some(Duration::from_secs(2)),
+        };
+        let mut times = Vec::new();
+        Screenshot::new(CaptureTarget::Primary)
+            .backend(backend())
+            .capture_series(&schedule, &clock, |_, _, metadata| {
+                times.push(metadata.capture_time.duration_since(UNIX_EPOCH).unwrap());
+                // Saving takes longer than the interval
+                clock.sleep(Duration::from_millis(700));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(times, [0, 700, 1400].map(Duration::from_millis));
+
+        let error = Screenshot::new(CaptureTarget::Primary)
+            .backend(backend().stalled())
+            .timeout(Duration::from_millis(10))
+            .capture_series(&schedule, &clock, |_, _, _| Ok(()))
+            .unwrap_err();
+        assert_eq!(error.exit_code(), 7);
+    }
+
+    #[test]
+    fn monitor_ids_start_at_one() {
+        let screenshot = |id| {
+            Screenshot::new(CaptureTarget::Monitor(id))
+                .backend(backend())
+                .capture()
+        };
+        assert_eq!(screenshot(0).unwrap_err().exit_code(), 3);
+        assert_eq!(screenshot(2).unwrap().width, 96);
+        assert_eq!(screenshot(3).unwrap_err().exit_code(), 3);
+    }
+
+    #[test]
+    fn primary_is_first_display() {
+        let frame = Screenshot::new(CaptureTarget::Primary)
+            .backend(backend())
+            .pixel_format(PixelFormat::Rgba16Float)
+            .capture()
+            .unwrap();
+        assert_eq!((frame.width, frame.height), (128, 72));
+        assert_eq!(frame.bytes.len(), 128 * 72 * 8);
+    }
+
+    #[test]
+    fn describes_capture_source() {
+        let (_, metadata) = Screenshot::new(CaptureTarget::Window("calc".to_owned()))
+            .backend(backend().with_dpi(120))
+            .capture_with_metadata()
+            .unwrap();
+        assert_eq!(metadata.window_title.as_deref(), Some("Calculator"));
+        assert_eq!(
+            metadata.window_class.as_deref(),
+            Some("ApplicationFrameWindow")
+        );
+        assert_eq!(metadata.monitor, None);
+        assert_eq!(metadata.dpi, Some(120));
+
+        let (_, metadata) = Screenshot::new(CaptureTarget::Monitor(2))
+            .backend(backend())
+            .capture_with_metadata()
+            .unwrap();
+        assert_eq!(metadata.window_title, None);
+        assert_eq!(metadata.monitor, Some(2));
+        assert_eq!(metadata.dpi, None);
+
+        let (_, metadata) = Screenshot::new(CaptureTarget::Primary)
+            .backend(backend())
+            .capture_with_metadata()
+            .unwrap();
+        assert_eq!(metadata.monitor, Some(1));
+    }
+
+    #[test]
+    fn region_spans_monitors() {
+        // 128x72 at (0, 0) and 96x54 at (128, 0)
+        let frame = Screenshot::new(CaptureTarget::Region(Rect::new(120, 10, 16, 4)))
+            .backend(backend())
+            .capture()
+            .unwrap();
+        assert_eq!((frame.width, frame.height), (16, 4));
+        // Each monitor's pattern starts again at its own top left corner
+        assert_eq!(frame.bytes[..4], test_pattern_pixel(120, 10));
+        assert_eq!(frame.bytes[8 * 4..9 * 4], test_pattern_pixel(0, 10));
+
+        let (_, metadata) = Screenshot::new(CaptureTarget::Region(Rect::new(130, 0, 8, 8)))
+            .backend(backend())
+            .capture_with_metadata()
+            .unwrap();
+        assert_eq!(metadata.monitor, Some(2));
+
+        let result = Screenshot::new(CaptureTarget::Region(Rect::new(0, 100, 8, 8)))
+            .backend(backend())
+            .capture();
+        assert_eq!(result.unwrap_err().exit_code(), 3);
+    }
+
+    #[test]
+    fn clips_region_to_desktop() {
+        for region in [
+            Rect::new(0, 0, 36_000, 30_000),
+            Rect::new(-70_000, -70_000, 140_000, 140_000),
+        ] {
+            let frame = Screenshot::new(CaptureTarget::Region(region))
+                .backend(backend())
+                .capture()
+                .unwrap();
+            assert_eq!((frame.width, frame.height), (224, 72));
+        }
+        let frame = Screenshot::new(CaptureTarget::Region(Rect::new(-8, 60, 16, 100)))
+            .backend(backend())
+            .capture()
+            .unwrap();
+        assert_eq!((frame.width, frame.height), (8, 12));
+        assert_eq!(frame.bytes[..4], test_pattern_pixel(0, 60));
+    }
+
+    #[test]
+    fn crops_captured_window() {
+        let frame = Screenshot::new(CaptureTarget::Window("calc".to_owned()))
+            .backend(backend())
+            .crop(Rect::new(2, 3, 4, 5))
+            .capture()
+            .unwrap();
+        assert_eq!((frame.width, frame.height), (4, 5));
+        assert_eq!(frame.bytes[..4], test_pattern_pixel(2, 3));
+
+        let result = Screenshot::new(CaptureTarget::Window("calc".to_owned()))
+            .backend(backend())
+            .crop(Rect::new(10, 10, 20, 20))
+            .capture();
+        assert_eq!(result.unwrap_err().exit_code(), 3);
+    }
+
+    #[test]
+    fn stitches_all_monitors() {
+        let backend = MockBackend::new()
+            .with_display(8, 6)
+            .with_display_at(-4, -2, 4, 4);
+        let frame = Screenshot::new(CaptureTarget::AllMonitors)
+            .backend(backend)
+            .capture()
+            .unwrap();
+        assert_eq!((frame.width, frame.height), (12, 8));
+        let pixel = |x: usize, y: usize| &frame.bytes[(y * 12 + x) * 4..(y * 12 + x + 1) * 4];
+        assert_eq!(pixel(0, 0), test_pattern_pixel(0, 0));
+        assert_eq!(pixel(4, 2), test_pattern_pixel(0, 0));
+        assert_eq!(pixel(11, 7), test_pattern_pixel(7, 5));
+        // The gap below the left monitor is transparent
+        assert_eq!(pixel(0, 7), [0; 4]);
+
+        let result = Screenshot::new(CaptureTarget::AllMonitors)
+            .backend(MockBackend::new())
+            .capture();
+        assert_eq!(result.unwrap_err().exit_code(), 3);
+    }
+
+    #[test]
+    fn parses_options_in_any_case() {
+        assert_eq!("PNG".parse(), Ok(ImageFormat::Png));
+        assert_eq!("RGBA16F".parse(), Ok(PixelFormat::Rgba16Float));
+        assert_eq!("WIC".parse(), Ok(Encoder::Wic));
+        assert_eq!("Paeth".parse(), Ok(PngFilter::Paeth));
+        assert_eq!("sRGB".parse(), Ok(ColorProfile::Srgb));
+        assert_eq!("PQ".parse(), Ok(PngTransfer::Pq));
+        assert_eq!("ZIP".parse(), Ok(ExrCompression::Zip));
+        assert_eq!("ACES".parse(), Ok(ToneMapOperator::AcesFilmic));
+        assert_eq!("Glob".parse(), Ok(MatchMode::Glob));
+        assert_eq!("Index:2".parse(), Ok(WindowSelection::Index(2)));
+        let error = "Bogus".parse::<Encoder>().unwrap_err();
+        assert!(error.starts_with("'Bogus' isn't"), "{}", error);
+    }
+}
@@ -0,0 +1,335 @@
+use screenshot::{AdvancedColor, DisplayInfo, Rect, WindowInfo};
+use std::io::Write;
+use std::str::FromStr;
+
+/// What the `list` subcommand prints.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ListKind {
+    Windows,
+    Monitors,
+}
+
+impl FromStr for ListKind {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "windows" => Ok(ListKind::Windows),
+            "monitors" => Ok(ListKind::Monitors),
+            _ => Err(format!(
+                "'{}' can't be listed, expecting windows or monitors",
+                input
+            )),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ListFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+impl FromStr for ListFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "table" => Ok(ListFormat::Table),
+            "json" => Ok(ListFormat::Json),
+            "csv" => Ok(ListFormat::Csv),
+            _ => Err(format!(
+                "'{}' isn't a list format, expecting table, json or csv",
+                input
+            )),
+        }
+    }
+}
+
+enum Field {
+    Text(Option<String>),
+    Number(f64),
+    Bool(bool),
+    Rect(Rect),
+}
+
+/// Rows of named fields that can be printed in any [`ListFormat`].
+pub struct Listing {
+    columns: &'static [&'static str],
+    rows: Vec<Vec<Field>>,
+}
+
+impl Listing {
+    pub fn windows(windows: &[WindowInfo], displays: &[DisplayInfo]) -> Self {
+        let rows = windows
+            .iter()
+            .enumerate()
+            .map(|(index, window)| {
+                vec![
+                    Field::Number(index as f64),
+                    Field::Text(Some(format!("{:#x}", window.handle))),
+                    Field::Number(window.pid as f64),
+                    Field::Text(window.process_name.clone()),
+                    Field::Text(Some(window.class_name.clone())),
+                    Field::Text(Some(window.title.clone())),
+                    Field::Rect(window.rect),
+                    window
+                        .monitor_id(displays)
+                        .map(|id| Field::Number(id as f64))
+                        .unwrap_or(Field::Text(None)),
+                ]
+            })
+            .collect();
+        Self {
+            columns: &[
+                "index", "hwnd", "pid", "process", "class", "title", "rect", "monitor",
+            ],
+            rows,
+        }
+    }
+
+    pub fn monitors(displays: &[DisplayInfo]) -> Self {
+        let rows = displays
+            .iter()
+            .enumerate()
+            .map(|(index, display)| {
+                let hdr = match display.advanced_color {
+                    AdvancedColor::Unsupported => "unsupported",
+                    AdvancedColor::Supported => "off",
+                    AdvancedColor::Enabled => "on",
+                };
+                vec![
+                    Field::Number((index + 1) as f64),
+                    Field::Text(Some(format!("{:#x}", display.handle))),
+                    Field::Text(Some(display.device_name.clone())),
+                    Field::Text(display.friendly_name.clone()),
+                    Field::Rect(display.rect),
+                    Field::Rect(display.work_area),
+                    Field::Number(display.dpi as f64),
+                    // Rounded so the f32 doesn't print as 1.0416666269302368.
+                    Field::Number((display.scale() as f64 * 100.0).round() / 100.0),
+                    Field::Bool(display.is_primary),
+                    Field::Text(Some(hdr.to_owned())),
+                ]
+            })
+            .collect();
+        Self {
+            columns: &[
+                "monitor",
+                "hmonitor",
+                "device",
+                "name",
+                "rect",
+                "work_area",
+                "dpi",
+                "scale",
+                "primary",
+                "hdr",
+            ],
+            rows,
+        }
+    }
+
+    pub fn write<W: Write>(&self, format: ListFormat, writer: &mut W) -> std::io::Result<()> {
+        match format {
+            ListFormat::Table => self.write_table(writer),
+            ListFormat::Json => self.write_json(writer),
+            ListFormat::Csv => self.write_csv(writer),
+        }
+    }
+
+    fn write_table<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let cells: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| row.iter().map(Field::to_text).collect())
+            .collect();
+        let widths: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(column, name)| {
+                cells
+                    .iter()
+                    .map(|row| row[column].chars().count())
+                    .chain([name.len()])
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let header: Vec<String> = self
+            .columns
+            .iter()
+            .map(|name| name.to_uppercase())
+            .collect();
+        for row in std::iter::once(&header).chain(&cells) {
+            let mut line = String::new();
+            for (column, cell) in row.iter().enumerate() {
+                if column + 1 == row.len() {
+                    line.push_str(cell);
+                } else {
+                    let padding = widths[column] - cell.chars().count();
+                    line.push_str(cell);
+                    line.extend(std::iter::repeat_n(' ', padding + 2));
+                }
+            }
+            writeln!(writer, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+
+    fn write_json<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writeln!(writer, "[")?;
+        for (index, row) in self.rows.iter().enumerate() {
+            let fields: Vec<String> = self
+                .columns
+                .iter()
+                .zip(row)
+                .map(|(name, field)| format!("\"{}\": {}", name, field.to_json()))
+                .collect();
+            let separator = if index + 1 < self.rows.len() { "," } else { "" };
+            writeln!(writer, "  {{ {} }}{}", fields.join(", "), separator)?;
+        }
+        writeln!(writer, "]")
+    }
+
+    fn write_csv<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writeln!(writer, "{}", self.columns.join(","))?;
+        for row in &self.rows {
+            let cells: Vec<String> = row
+                .iter()
+                .map(|field| csv_escape(&field.to_text()))
+                .collect();
+            writeln!(writer, "{}", cells.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+impl Field {
+    /// Rectangles are written the way `--region` and `--crop` take them.
+    fn to_text(&self) -> String {
+        match self {
+            Field::Text(text) => text.clone().unwrap_or_default(),
+            Field::Number(number) => number.to_string(),
+            Field::Bool(value) => value.to_string(),
+            Field::Rect(rect) => format!("{},{},{},{}", rect.x, rect.y, rect.width, rect.height),
+        }
+    }
+
+    fn to_json(&self) -> String {
+        match self {
+            Field::Text(Some(text)) => json_escape(text),
+            Field::Text(None) => "null".to_owned(),
+            Field::Number(_) | Field::Bool(_) => self.to_text(),
+            Field::Rect(rect) => format!(
+                "{{ \"x\": {}, \"y\": {}, \"width\": {}, \"height\": {} }}",
+                rect.x, rect.y, rect.width, rect.height
+            ),
+        }
+    }
+}
+
+fn json_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn csv_escape(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(dpi: u32) -> DisplayInfo {
+        let rect = Rect::new(0, 0, 128, 72);
+        DisplayInfo {
+            handle: 0x20000,
+            rect,
+            work_area: rect,
+            device_name: r"\\.\DISPLAY1".to_owned(),
+            friendly_name: None,
+            dpi,
+            is_primary: true,
+            advanced_color: AdvancedColor::Unsupported,
+        }
+    }
+
+    fn listing() -> Listing {
+        let window = WindowInfo {
+            handle: 0x10000,
+            title: "Say \"hi\", world".to_owned(),
+            class_name: "Notepad".to_owned(),
+            pid: 1000,
+            process_name: None,
+            rect: Rect::new(0, 0, 64, 32),
+        };
+        Listing::windows(&[window], &[display(96)])
+    }
+
+    fn write(format: ListFormat) -> String {
+        let mut output = Vec::new();
+        listing().write(format, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn writes_json() {
+        assert_eq!(
+            write(ListFormat::Json),
+            "[\n  { \"index\": 0, \"hwnd\": \"0x10000\", \"pid\": 1000, \"process\": null, \
+             \"class\": \"Notepad\", \"title\": \"Say \\\"hi\\\", world\", \
+             \"rect\": { \"x\": 0, \"y\": 0, \"width\": 64, \"height\": 32 }, \"monitor\": 1 }\n]\n"
+        );
+    }
+
+    #[test]
+    fn writes_csv() {
+        assert_eq!(
+            write(ListFormat::Csv),
+            "index,hwnd,pid,process,class,title,rect,monitor\n\
+             0,0x10000,1000,,Notepad,\"Say \"\"hi\"\", world\",\"0,0,64,32\",1\n"
+        );
+    }
+
+    #[test]
+    fn writes_table() {
+        let table = write(ListFormat::Table);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("INDEX  HWND     PID   PROCESS  CLASS    TITLE"));
+        assert!(lines[1].ends_with("0,0,64,32  1"));
+    }
+
+    #[test]
+    fn rounds_display_scale() {
+        let mut output = Vec::new();
+        Listing::monitors(&[display(100), display(144)])
+            .write(ListFormat::Csv, &mut output)
+            .unwrap();
+        let csv = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert!(lines[1].contains(",100,1.04,"), "{}", lines[1]);
+        assert!(lines[2].contains(",144,1.5,"), "{}", lines[2]);
+    }
+}
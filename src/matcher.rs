@@ -0,0 +1,170 @@
+use crate::window_info::WindowInfo;
+use crate::{Result, ScreenshotError};
+use regex::{Regex, RegexBuilder};
+use std::str::FromStr;
+
+/// How a window query is compared against window titles.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MatchMode {
+    /// The title contains the query.
+    #[default]
+    Substring,
+    /// The title is the query.
+    Exact,
+    /// The whole title matches a pattern where `*` is any text, `?` any
+    /// character and `[...]` one of a set of characters.
+    Glob,
+    /// The title contains a match of a regular expression.
+    Regex,
+}
+
+impl FromStr for MatchMode {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "substring" => Ok(MatchMode::Substring),
+            "exact" => Ok(MatchMode::Exact),
+            "glob" => Ok(MatchMode::Glob),
+            "regex" => Ok(MatchMode::Regex),
+            _ => Err(format!(
+                "'{}' isn't a match mode, expecting substring, exact, glob or regex",
+                input
+            )),
+        }
+    }
+}
+
+/// Decides whether a window title matches a query.
+#[derive(Clone, Debug)]
+pub struct TitleMatcher {
+    regex: Regex,
+}
+
+impl TitleMatcher {
+    pub fn new(query: &str, mode: MatchMode, case_sensitive: bool) -> Result<Self> {
+        let pattern = match mode {
+            MatchMode::Substring => regex::escape(query),
+            MatchMode::Exact => format!("^{}$", regex::escape(query)),
+            MatchMode::Glob => format!("^{}$", glob_to_regex(query)),
+            MatchMode::Regex => query.to_owned(),
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(!case_sensitive)
+            .build()
+            .map_err(|error| {
+                ScreenshotError::InvalidTarget(format!(
+                    "'{}' isn't a valid pattern: {}",
+                    query, error
+                ))
+            })?;
+        Ok(Self { regex })
+    }
+
+    pub fn is_match(&self, title: &str) -> bool {
+        self.regex.is_match(title)
+    }
+
+    /// Returns the windows whose title matches.
+    pub fn filter(&self, windows: &[WindowInfo]) -> Vec<WindowInfo> {
+        windows
+            .iter()
+            .filter(|window| self.is_match(&window.title))
+            .cloned()
+            .collect()
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::new();
+    let mut chars = glob.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            '[' => {
+                let class: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                pattern.push('[');
+                match class.strip_prefix('!') {
+                    Some(negated) => {
+                        pattern.push('^');
+                        pattern.push_str(&escape_class(negated));
+                    }
+                    None => pattern.push_str(&escape_class(&class)),
+                }
+                pattern.push(']');
+            }
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern
+}
+
+/// Escapes everything in a glob character class except ranges.
+fn escape_class(class: &str) -> String {
+    class
+        .chars()
+        .map(|c| match c {
+            '-' => "-".to_owned(),
+            c => regex::escape(&c.to_string()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Matches ignoring case, or case sensitively if `query` starts with `=`.
+    fn matcher(mode: MatchMode) -> impl Fn(&str, &str) -> bool {
+        move |query, title| {
+            let (query, case_sensitive) = match query.strip_prefix('=') {
+                Some(query) => (query, true),
+                None => (query, false),
+            };
+            TitleMatcher::new(query, mode, case_sensitive)
+                .unwrap()
+                .is_match(title)
+        }
+    }
+
+    #[test]
+    fn matches_substrings() {
+        let matches = matcher(MatchMode::Substring);
+        assert!(matches("notepad", "Untitled - Notepad"));
+        assert!(matches("notepad", "Notepad++"));
+        assert!(!matches("=notepad", "Notepad++"));
+        // Regex syntax in the query is taken literally
+        assert!(matches("=C++ (x64)", "Visual C++ (x64)"));
+    }
+
+    #[test]
+    fn matches_exact_titles() {
+        let matches = matcher(MatchMode::Exact);
+        assert!(matches("notepad++", "Notepad++"));
+        assert!(!matches("Notepad", "Untitled - Notepad"));
+        assert!(!matches("=notepad++", "Notepad++"));
+    }
+
+    #[test]
+    fn matches_globs() {
+        let matches = matcher(MatchMode::Glob);
+        assert!(matches("=* - Notepad", "Untitled - Notepad"));
+        assert!(!matches("=* - Notepad", "Notepad++"));
+        assert!(matches("=Notepad??", "Notepad++"));
+        assert!(matches("=Page [0-9]*", "Page 3 of 4"));
+        assert!(!matches("=Page [!0-9]*", "Page 3 of 4"));
+        assert!(matches("*.TXT", "notes.txt"));
+        assert!(!matches("*.TXT", "notes_txt"));
+    }
+
+    #[test]
+    fn matches_regexes() {
+        let matches = matcher(MatchMode::Regex);
+        assert!(matches("^Notepad$", "notepad"));
+        assert!(matches(r"=\d+ unread", "Inbox (12 unread)"));
+        assert!(!matches("^Notepad$", "Notepad++"));
+        let error = TitleMatcher::new("(", MatchMode::Regex, false).unwrap_err();
+        assert_eq!(error.exit_code(), 3);
+    }
+}
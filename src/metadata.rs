@@ -0,0 +1,120 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where and when a capture was taken. Written into image formats that can hold it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Metadata {
+    pub capture_time: SystemTime,
+    pub window_title: Option<String>,
+    pub window_class: Option<String>,
+    /// The executable of the process owning the captured window, if it's known.
+    pub process_name: Option<String>,
+    /// The 1-based monitor id, if a single monitor was captured.
+    pub monitor: Option<usize>,
+    /// The name and version of the tool that took the capture.
+    pub software: String,
+    /// The dots per inch of the monitor the capture was taken on.
+    pub dpi: Option<u32>,
+}
+
+impl Metadata {
+    /// Metadata for a capture taken now, without any source information.
+    pub fn now() -> Self {
+        Self {
+            capture_time: SystemTime::now(),
+            window_title: None,
+            window_class: None,
+            process_name: None,
+            monitor: None,
+            software: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            dpi: None,
+        }
+    }
+
+    /// Keyword and text pairs, using the registered PNG keywords where one fits.
+    pub fn text_entries(&self) -> Vec<(&'static str, String)> {
+        let mut entries = vec![("Creation Time", format_timestamp(self.capture_time))];
+        if let Some(title) = &self.window_title {
+            entries.push(("Title", title.clone()));
+        }
+        if let Some(class_name) = &self.window_class {
+            entries.push(("Window Class", class_name.clone()));
+        }
+        if let Some(monitor) = self.monitor {
+            entries.push(("Monitor", monitor.to_string()));
+        }
+        entries.push(("Software", self.software.clone()));
+        entries
+    }
+
+    /// The DPI in pixels per meter, as stored by PNG's pHYs chunk.
+    pub fn pixels_per_meter(&self) -> Option<u32> {
+        self.dpi.map(|dpi| (dpi as f64 / 0.0254).round() as u32)
+    }
+}
+
+/// Formats `time` as an RFC 3339 UTC timestamp, e.g. `2024-05-01T13:37:00Z`.
+pub fn format_timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let (days, seconds) = (seconds / 86_400, seconds % 86_400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Formats `time` for use in file names, e.g. `2024-05-01T13-37-00.250Z`. Like
+/// [`format_timestamp`], but with milliseconds and without the colons Windows
+/// doesn't allow.
+pub fn filename_timestamp(time: SystemTime) -> String {
+    let milliseconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.subsec_millis())
+        .unwrap_or(0);
+    let timestamp = format_timestamp(time).replace(':', "-");
+    format!("{}.{:03}Z", timestamp.trim_end_matches('Z'), milliseconds)
+}
+
+/// Converts days since 1970-01-01 into a proleptic Gregorian date, following
+/// Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn formats_timestamps() {
+        let at = |seconds| UNIX_EPOCH + Duration::from_secs(seconds);
+        assert_eq!(format_timestamp(at(0)), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(at(951_782_400)), "2000-02-29T00:00:00Z");
+        assert_eq!(format_timestamp(at(1_714_570_620)), "2024-05-01T13:37:00Z");
+        let at = at(1_714_570_620) + Duration::from_millis(250);
+        assert_eq!(filename_timestamp(at), "2024-05-01T13-37-00.250Z");
+    }
+}
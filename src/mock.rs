@@ -0,0 +1,341 @@
+use crate::backend::{receive_frame, CaptureBackend, CaptureSession, CaptureSource};
+use crate::display_info::{AdvancedColor, DisplayInfo};
+use crate::frame::{Frame, PixelFormat};
+use crate::region::Rect;
+use crate::schedule::Clock;
+use crate::window_info::WindowInfo;
+use crate::{Result, ScreenshotError};
+use half::f16;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::mpsc::channel;
+use std::time::{Duration, SystemTime};
+
+/// A deterministic in-memory backend with scripted windows and monitors. Every
+/// capture returns [`test_pattern`] at the size the source was registered with.
+#[derive(Clone, Debug, Default)]
+pub struct MockBackend {
+    windows: Vec<(WindowInfo, (u32, u32))>,
+    displays: Vec<(DisplayInfo, (u32, u32))>,
+    dpi: Option<u32>,
+    foreground: Option<isize>,
+    under_cursor: Option<isize>,
+    delivery: FrameDelivery,
+    /// Windows left out of the first few enumerations, by handle.
+    late_windows: Vec<(isize, usize)>,
+    enumerations: Cell<usize>,
+    /// How many capture sessions were started, shared between clones.
+    sessions: Rc<Cell<usize>>,
+}
+
+/// When the mock's frame pool hands over a captured frame.
+#[derive(Clone, Copy, Debug, Default)]
+enum FrameDelivery {
+    #[default]
+    Immediate,
+    After(Duration),
+    Never,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_window(mut self, title: &str, class_name: &str, width: u32, height: u32) -> Self {
+        let index = self.windows.len();
+        let window = WindowInfo {
+            handle: 0x10000 + index as isize * 0x10,
+            title: title.to_owned(),
+            class_name: class_name.to_owned(),
+            pid: 1000 + index as u32,
+            process_name: None,
+            rect: Rect::new(0, 0, width, height),
+        };
+        self.windows.push((window, (width, height)));
+        self
+    }
+
+    /// Adds a monitor to the right of the previous one. The first monitor added
+    /// is the primary one.
+    pub fn with_display(self, width: u32, height: u32) -> Self {
+        let x = self
+            .displays
+            .last()
+            .map(|(info, _)| info.rect.right() as i32)
+            .unwrap_or(0);
+        self.with_display_at(x, 0, width, height)
+    }
+
+    /// Adds a 96 DPI SDR monitor at a position on the virtual desktop.
+    pub fn with_display_at(mut self, x: i32, y: i32, width: u32, height: u32) -> Self {
+        let index = self.displays.len();
+        let rect = Rect::new(x, y, width, height);
+        let display = DisplayInfo {
+            handle: 0x20000 + index as isize * 0x10,
+            rect,
+            work_area: rect,
+            device_name: format!(r"\\.\DISPLAY{}", index + 1),
+            friendly_name: Some(format!("Mock Display {}", index + 1)),
+            dpi: 96,
+            is_primary: index == 0,
+            advanced_color: AdvancedColor::Unsupported,
+        };
+        self.displays.push((display, (width, height)));
+        self
+    }
+
+    /// Adds a window that only shows up from the `enumerations`th time windows
+    /// are enumerated, counting from 0.
+    pub fn with_late_window(
+        self,
+        title: &str,
+        class_name: &str,
+        width: u32,
+        height: u32,
+        enumerations: usize,
+    ) -> Self {
+        let mut backend = self.with_window(title, class_name, width, height);
+        let handle = backend.windows.last().unwrap().0.handle;
+        backend.late_windows.push((handle, enumerations));
+        backend
+    }
+
+    /// Sets the process owning the last window added.
+    pub fn with_process(mut self, pid: u32, name: &str) -> Self {
+        if let Some((window, _)) = self.windows.last_mut() {
+            window.pid = pid;
+            window.process_name = Some(name.to_owned());
+        }
+        self
+    }
+
+    /// Makes the window titled `title` the foreground window.
+    pub fn with_foreground(mut self, title: &str) -> Self {
+        self.foreground = self.handle_of(title);
+        self
+    }
+
+    /// Puts the mouse cursor over the window titled `title`.
+    pub fn with_cursor_over(mut self, title: &str) -> Self {
+        self.under_cursor = self.handle_of(title);
+        self
+    }
+
+    /// Delivers each frame `delay` after the capture starts.
+    pub fn with_frame_delay(mut self, delay: Duration) -> Self {
+        self.delivery = FrameDelivery::After(delay);
+        self
+    }
+
+    /// Never delivers a frame, like a minimized window.
+    pub fn stalled(mut self) -> Self {
+        self.delivery = FrameDelivery::Never;
+        self
+    }
+
+    /// Reports `dpi` for every capture source.
+    pub fn with_dpi(mut self, dpi: u32) -> Self {
+        self.dpi = Some(dpi);
+        self
+    }
+
+    /// How many capture sessions this backend and its clones have started.
+    pub fn sessions_started(&self) -> usize {
+        self.sessions.get()
+    }
+
+    fn handle_of(&self, title: &str) -> Option<isize> {
+        self.windows
+            .iter()
+            .find(|(info, _)| info.title == title)
+            .map(|(info, _)| info.handle)
+    }
+
+    fn window(&self, handle: Option<isize>) -> Option<WindowInfo> {
+        self.windows
+            .iter()
+            .find(|(info, _)| Some(info.handle) == handle)
+            .map(|(info, _)| info.clone())
+    }
+
+    fn size_of(&self, source: &CaptureSource) -> Option<(u32, u32)> {
+        match source {
+            CaptureSource::Window(window) => self
+                .windows
+                .iter()
+                .find(|(info, _)| info.handle == window.handle)
+                .map(|(_, size)| *size),
+            CaptureSource::Monitor(display) => self
+                .displays
+                .iter()
+                .find(|(info, _)| info.handle == display.handle)
+                .map(|(_, size)| *size),
+            CaptureSource::Primary => self
+                .displays
+                .iter()
+                .find(|(info, _)| info.is_primary)
+                .map(|(_, size)| *size),
+        }
+    }
+}
+
+impl CaptureBackend for MockBackend {
+    fn enumerate_windows(&self) -> Result<Vec<WindowInfo>> {
+        let enumeration = self.enumerations.replace(self.enumerations.get() + 1);
+        let has_appeared = |info: &WindowInfo| {
+            self.late_windows
+                .iter()
+                .all(|(handle, from)| *handle != info.handle || enumeration >= *from)
+        };
+        Ok(self
+            .windows
+            .iter()
+            .map(|(info, _)| info.clone())
+            .filter(has_appeared)
+            .collect())
+    }
+
+    fn enumerate_displays(&self) -> Result<Vec<DisplayInfo>> {
+        Ok(self.displays.iter().map(|(info, _)| info.clone()).collect())
+    }
+
+    fn capture(
+        &self,
+        source: &CaptureSource,
+        pixel_format: PixelFormat,
+        timeout: Duration,
+    ) -> Result<Frame> {
+        let (width, height) = self.size_of(source).ok_or_else(|| {
+            ScreenshotError::InvalidTarget(
+                "the mock backend doesn't know about this capture source!".to_owned(),
+            )
+        })?;
+
+        // Hand the frame over through a channel, like the frame pool callback does
+        let (sender, receiver) = channel();
+        let frame = test_pattern(width, height, pixel_format);
+        let _stalled_sender = match self.delivery {
+            FrameDelivery::Immediate => {
+                let _ = sender.send(frame);
+                None
+            }
+            FrameDelivery::After(delay) => {
+                std::thread::spawn(move || {
+                    std::thread::sleep(delay);
+                    let _ = sender.send(frame);
+                });
+                None
+            }
+            FrameDelivery::Never => Some(sender),
+        };
+        receive_frame(&receiver, timeout)
+    }
+
+    fn start_session<'a>(
+        &'a self,
+        source: &CaptureSource,
+        pixel_format: PixelFormat,
+    ) -> Result<Box<dyn CaptureSession + 'a>> {
+        self.sessions.set(self.sessions.get() + 1);
+        Ok(Box::new(MockSession {
+            backend: self,
+            source: source.clone(),
+            pixel_format,
+        }))
+    }
+
+    fn dpi(&self, _source: &CaptureSource) -> Result<Option<u32>> {
+        Ok(self.dpi)
+    }
+
+    fn foreground_window(&self) -> Result<Option<WindowInfo>> {
+        Ok(self.window(self.foreground))
+    }
+
+    fn window_under_cursor(&self) -> Result<Option<WindowInfo>> {
+        Ok(self.window(self.under_cursor))
+    }
+}
+
+struct MockSession<'a> {
+    backend: &'a MockBackend,
+    source: CaptureSource,
+    pixel_format: PixelFormat,
+}
+
+impl CaptureSession for MockSession<'_> {
+    fn next_frame(&mut self, timeout: Duration) -> Result<Frame> {
+        self.backend
+            .capture(&self.source, self.pixel_format, timeout)
+    }
+}
+
+/// A clock that only moves when something sleeps on it.
+#[derive(Debug)]
+pub struct MockClock {
+    now: Cell<SystemTime>,
+}
+
+impl MockClock {
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            now: Cell::new(start),
+        }
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        self.now.get()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+}
+
+/// Returns the BGRA8 color of the test pattern at `(x, y)`.
+pub fn test_pattern_pixel(x: u32, y: u32) -> [u8; 4] {
+    [x as u8, y as u8, (x ^ y) as u8, 255]
+}
+
+/// A frame where every pixel is [`test_pattern_pixel`]. FP16 frames hold the
+/// same colors, normalized to `0.0..=1.0` and converted to RGBA order.
+pub fn test_pattern(width: u32, height: u32, format: PixelFormat) -> Frame {
+    let mut bytes = Vec::with_capacity((width * height * format.bytes_per_pixel()) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let [b, g, r, a] = test_pattern_pixel(x, y);
+            match format {
+                PixelFormat::Bgra8 => bytes.extend_from_slice(&[b, g, r, a]),
+                PixelFormat::Rgba16Float => {
+                    for value in [r, g, b, a] {
+                        let value = f16::from_f32(value as f32 / 255.0);
+                        bytes.extend_from_slice(&value.to_le_bytes());
+                    }
+                }
+            }
+        }
+    }
+    Frame::new(width, height, format, bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_displays() {
+        let displays = MockBackend::new()
+            .with_display(128, 72)
+            .with_display(96, 54)
+            .enumerate_displays()
+            .unwrap();
+        assert_eq!(displays[1].device_name, r"\\.\DISPLAY2");
+        assert_eq!(displays[1].rect, Rect::new(128, 0, 96, 54));
+        assert_eq!(displays[1].scale(), 1.0);
+        assert!(displays[0].is_primary && !displays[1].is_primary);
+        assert!(!displays[0].is_hdr());
+    }
+}
@@ -0,0 +1,374 @@
+use crate::frame::{Frame, PixelFormat};
+use crate::{Result, ScreenshotError};
+use std::str::FromStr;
+
+/// A rectangle in pixels. Monitor and region rectangles are in virtual-desktop
+/// coordinates, where the primary monitor starts at `(0, 0)`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn right(&self) -> i64 {
+        self.x as i64 + self.width as i64
+    }
+
+    pub fn bottom(&self) -> i64 {
+        self.y as i64 + self.height as i64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// The overlap of both rectangles, if they overlap at all.
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if right <= x as i64 || bottom <= y as i64 {
+            return None;
+        }
+        Some(Rect::new(
+            x,
+            y,
+            (right - x as i64) as u32,
+            (bottom - y as i64) as u32,
+        ))
+    }
+
+    /// The smallest rectangle containing both.
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = self.right().max(other.right());
+        let bottom = self.bottom().max(other.bottom());
+        Rect::new(x, y, (right - x as i64) as u32, (bottom - y as i64) as u32)
+    }
+
+    pub fn contains(&self, other: &Rect) -> bool {
+        other.x >= self.x
+            && other.y >= self.y
+            && other.right() <= self.right()
+            && other.bottom() <= self.bottom()
+    }
+
+    /// This rectangle moved so that `origin` becomes `(0, 0)`.
+    pub fn relative_to(&self, origin: &Rect) -> Rect {
+        Rect::new(
+            (self.x as i64 - origin.x as i64) as i32,
+            (self.y as i64 - origin.y as i64) as i32,
+            self.width,
+            self.height,
+        )
+    }
+}
+
+impl FromStr for Rect {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        let error = || format!("'{}' isn't a rectangle, expecting x,y,width,height", input);
+        let parts: Vec<&str> = input.split(',').map(str::trim).collect();
+        let [x, y, width, height] = parts[..] else {
+            return Err(error());
+        };
+        let rect = Rect::new(
+            x.parse().map_err(|_| error())?,
+            y.parse().map_err(|_| error())?,
+            width.parse().map_err(|_| error())?,
+            height.parse().map_err(|_| error())?,
+        );
+        if rect.is_empty() {
+            return Err(format!("'{}' is an empty rectangle", input));
+        }
+        Ok(rect)
+    }
+}
+
+/// Copies the pixels inside `rect`, given relative to the frame's top left corner.
+pub fn crop_frame(frame: &Frame, rect: &Rect) -> Result<Frame> {
+    let bounds = Rect::new(0, 0, frame.width, frame.height);
+    if rect.is_empty() || !bounds.contains(rect) {
+        return Err(ScreenshotError::InvalidTarget(format!(
+            "the crop {},{},{},{} isn't inside the {}x{} capture!",
+            rect.x, rect.y, rect.width, rect.height, frame.width, frame.height
+        )));
+    }
+
+    let bytes_per_pixel = frame.format.bytes_per_pixel() as usize;
+    let stride = frame.width as usize * bytes_per_pixel;
+    let row_length = rect.width as usize * bytes_per_pixel;
+    let mut bytes = Vec::with_capacity(buffer_len(rect, frame.format)?);
+    for row in rect.y as usize..rect.bottom() as usize {
+        let begin = row * stride + rect.x as usize * bytes_per_pixel;
+        bytes.extend_from_slice(&frame.bytes[begin..begin + row_length]);
+    }
+    Ok(Frame::new(rect.width, rect.height, frame.format, bytes))
+}
+
+/// The smallest rectangle containing all of `rects`, e.g. the virtual desktop
+/// from the monitor rectangles.
+pub fn bounding_rect(rects: &[Rect]) -> Option<Rect> {
+    let (first, rest) = rects.split_first()?;
+    Some(rest.iter().fold(*first, |bounds, rect| bounds.union(rect)))
+}
+
+/// Draws frames at their rectangles onto a transparent canvas covering `bounds`.
+/// Parts of a frame outside `bounds` are clipped, and later frames draw over
+/// earlier ones. All frames must share a pixel format.
+///
+/// A frame whose size doesn't match its rectangle, e.g. because the rectangle
+/// is in DPI-scaled coordinates, is resized to fit it with nearest neighbour sampling.
+pub fn compose_frames(frames: &[(Rect, Frame)], bounds: &Rect) -> Result<Frame> {
+    let Some((_, first)) = frames.first() else {
+        return Err(ScreenshotError::InvalidTarget(
+            "there are no frames to compose!".to_owned(),
+        ));
+    };
+    let format = first.format;
+    if let Some((_, frame)) = frames.iter().find(|(_, frame)| frame.format != format) {
+        return Err(ScreenshotError::UnsupportedFormat(format!(
+            "can't compose {:?} and {:?} frames!",
+            format, frame.format
+        )));
+    }
+
+    let bytes_per_pixel = format.bytes_per_pixel() as usize;
+    let stride = bounds.width as usize * bytes_per_pixel;
+    let mut bytes = vec![0u8; buffer_len(bounds, format)?];
+    for (rect, frame) in frames {
+        let Some(visible) = rect.intersection(bounds) else {
+            continue;
+        };
+        let source = visible.relative_to(rect);
+        let target = visible.relative_to(bounds);
+        let scaled = (frame.width, frame.height) != (rect.width, rect.height);
+        let frame_stride = frame.width as usize * bytes_per_pixel;
+        let row_length = visible.width as usize * bytes_per_pixel;
+        for row in 0..visible.height as usize {
+            let target_begin =
+                (target.y as usize + row) * stride + target.x as usize * bytes_per_pixel;
+            let target_row = &mut bytes[target_begin..target_begin + row_length];
+            if !scaled {
+                let source_begin =
+                    (source.y as usize + row) * frame_stride + source.x as usize * bytes_per_pixel;
+                target_row.copy_from_slice(&frame.bytes[source_begin..source_begin + row_length]);
+                continue;
+            }
+            let source_y = scale(source.y as usize + row, rect.height, frame.height);
+            for (column, pixel) in target_row.chunks_exact_mut(bytes_per_pixel).enumerate() {
+                let source_x = scale(source.x as usize + column, rect.width, frame.width);
+                let source_begin = source_y * frame_stride + source_x * bytes_per_pixel;
+                pixel.copy_from_slice(&frame.bytes[source_begin..source_begin + bytes_per_pixel]);
+            }
+        }
+    }
+    Ok(Frame::new(bounds.width, bounds.height, format, bytes))
+}
+
+/// The size of the buffer of a frame covering `rect`, failing if it's too large to allocate.
+fn buffer_len(rect: &Rect, format: PixelFormat) -> Result<usize> {
+    Frame::byte_len(rect.width, rect.height, format)
+        .filter(|len| *len <= isize::MAX as usize)
+        .ok_or_else(|| {
+            ScreenshotError::InvalidTarget(format!(
+                "a {}x{} image is too large!",
+                rect.width, rect.height
+            ))
+        })
+}
+
+/// Maps a pixel index from a `from` sized axis onto the center of a `to` sized one.
+fn scale(index: usize, from: u32, to: u32) -> usize {
+    let scaled = (index as u64 * 2 + 1) * to as u64 / (from as u64 * 2);
+    (scaled as usize).min(to as usize - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::PixelFormat;
+    use crate::mock::{test_pattern, test_pattern_pixel};
+
+    fn pixel(frame: &Frame, x: u32, y: u32) -> &[u8] {
+        let bytes_per_pixel = frame.format.bytes_per_pixel() as usize;
+        let begin = (y * frame.stride()) as usize + x as usize * bytes_per_pixel;
+        &frame.bytes[begin..begin + bytes_per_pixel]
+    }
+
+    fn solid(width: u32, height: u32, value: u8) -> Frame {
+        let bytes = vec![value; (width * height * 4) as usize];
+        Frame::new(width, height, PixelFormat::Bgra8, bytes)
+    }
+
+    #[test]
+    fn parses_rectangles() {
+        assert_eq!("-1920,0,100,50".parse(), Ok(Rect::new(-1920, 0, 100, 50)));
+        assert_eq!(" 1, 2, 3, 4".parse(), Ok(Rect::new(1, 2, 3, 4)));
+        assert!("1,2,3".parse::<Rect>().is_err());
+        assert!("1,2,3,4,5".parse::<Rect>().is_err());
+        assert!("1,2,-3,4".parse::<Rect>().is_err());
+        assert!("1,2,0,4".parse::<Rect>().is_err());
+    }
+
+    #[test]
+    fn intersects_rectangles() {
+        let a = Rect::new(0, 0, 100, 100);
+        assert_eq!(
+            a.intersection(&Rect::new(50, -10, 100, 20)),
+            Some(Rect::new(50, 0, 50, 10))
+        );
+        assert_eq!(a.intersection(&Rect::new(100, 0, 10, 10)), None);
+        assert_eq!(
+            a.union(&Rect::new(-20, 50, 10, 100)),
+            Rect::new(-20, 0, 120, 150)
+        );
+    }
+
+    #[test]
+    fn crops_both_pixel_formats() {
+        for format in [PixelFormat::Bgra8, PixelFormat::Rgba16Float] {
+            let frame = test_pattern(40, 30, format);
+            let cropped = crop_frame(&frame, &Rect::new(5, 7, 10, 3)).unwrap();
+            assert_eq!((cropped.width, cropped.height), (10, 3));
+            for y in 0..3 {
+                for x in 0..10 {
+                    assert_eq!(pixel(&cropped, x, y), pixel(&frame, x + 5, y + 7));
+                }
+            }
+        }
+
+        let frame = test_pattern(40, 30, PixelFormat::Bgra8);
+        let whole = crop_frame(&frame, &Rect::new(0, 0, 40, 30)).unwrap();
+        assert_eq!(whole.bytes, frame.bytes);
+        assert_eq!(pixel(&whole, 39, 29), test_pattern_pixel(39, 29));
+    }
+
+    #[test]
+    fn rejects_crops_outside_the_frame() {
+        let frame = test_pattern(40, 30, PixelFormat::Bgra8);
+        for rect in [
+            Rect::new(35, 0, 10, 10),
+            Rect::new(-1, 0, 10, 10),
+            Rect::new(0, 0, 0, 10),
+        ] {
+            let error = crop_frame(&frame, &rect).unwrap_err();
+            assert_eq!(error.exit_code(), 3);
+        }
+    }
+
+    #[test]
+    fn composes_and_clips_frames() {
+        // Two monitors side by side, the left one at negative coordinates
+        let frames = [
+            (Rect::new(-4, 0, 4, 2), solid(4, 2, 1)),
+            (Rect::new(0, 0, 4, 2), solid(4, 2, 2)),
+        ];
+        let composed = compose_frames(&frames, &Rect::new(-2, 1, 4, 2)).unwrap();
+        assert_eq!((composed.width, composed.height), (4, 2));
+        assert_eq!(pixel(&composed, 0, 0), [1; 4]);
+        assert_eq!(pixel(&composed, 1, 0), [1; 4]);
+        assert_eq!(pixel(&composed, 2, 0), [2; 4]);
+        assert_eq!(pixel(&composed, 3, 0), [2; 4]);
+        // Below the monitors is left transparent
+        assert_eq!(pixel(&composed, 0, 1), [0; 4]);
+    }
+
+    #[test]
+    fn rejects_oversized_canvas() {
+        let frames = [(Rect::new(0, 0, 2, 2), solid(2, 2, 1))];
+        let bounds = Rect::new(0, 0, u32::MAX, u32::MAX);
+        let result = compose_frames(&frames, &bounds);
+        assert!(matches!(result, Err(ScreenshotError::InvalidTarget(_))));
+        assert_eq!(
+            Frame::byte_len(u32::MAX, u32::MAX, PixelFormat::Bgra8),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_mixed_formats() {
+        let frames = [
+            (
+                Rect::new(0, 0, 2, 2),
+                test_pattern(2, 2, PixelFormat::Bgra8),
+            ),
+            (
+                Rect::new(2, 0, 2, 2),
+                test_pattern(2, 2, PixelFormat::Rgba16Float),
+            ),
+        ];
+        let result = compose_frames(&frames, &Rect::new(0, 0, 4, 2));
+        assert!(matches!(result, Err(ScreenshotError::UnsupportedFormat(_))));
+        assert!(compose_frames(&[], &Rect::new(0, 0, 4, 2)).is_err());
+    }
+
+    #[test]
+    fn bounds_odd_layouts() {
+        assert_eq!(bounding_rect(&[]), None);
+        // A portrait monitor left of the primary, and one above it
+        let rects = [
+            Rect::new(0, 0, 1920, 1080),
+            Rect::new(-1080, -840, 1080, 1920),
+            Rect::new(320, -1440, 2560, 1440),
+        ];
+        assert_eq!(
+            bounding_rect(&rects),
+            Some(Rect::new(-1080, -1440, 3960, 2520))
+        );
+    }
+
+    #[test]
+    fn composes_negative_origins_with_gaps() {
+        // An L shaped layout: the second monitor sits above and left of the
+        // first, leaving the top right and bottom left corners uncovered
+        let frames = [
+            (Rect::new(0, 0, 2, 2), solid(2, 2, 1)),
+            (Rect::new(-2, -2, 2, 2), solid(2, 2, 2)),
+        ];
+        let bounds =
+            bounding_rect(&frames.iter().map(|(rect, _)| *rect).collect::<Vec<_>>()).unwrap();
+        let composed = compose_frames(&frames, &bounds).unwrap();
+        assert_eq!((composed.width, composed.height), (4, 4));
+        let expected = [[2, 2, 0, 0], [2, 2, 0, 0], [0, 0, 1, 1], [0, 0, 1, 1]];
+        for (y, row) in expected.iter().enumerate() {
+            for (x, value) in row.iter().enumerate() {
+                assert_eq!(pixel(&composed, x as u32, y as u32), [*value; 4]);
+            }
+        }
+    }
+
+    #[test]
+    fn composes_mixed_dpi() {
+        // A 200% monitor reports a 4x4 rectangle but captures 8x8 pixels
+        let high_dpi = test_pattern(8, 8, PixelFormat::Bgra8);
+        let frames = [
+            (Rect::new(0, 0, 4, 4), high_dpi),
+            (Rect::new(4, 0, 2, 2), solid(2, 2, 7)),
+        ];
+        let composed = compose_frames(&frames, &Rect::new(0, 0, 6, 4)).unwrap();
+        for y in 0..4 {
+            for x in 0..4 {
+                let expected = test_pattern_pixel(x * 2 + 1, y * 2 + 1);
+                assert_eq!(pixel(&composed, x, y), expected);
+            }
+        }
+        assert_eq!(pixel(&composed, 5, 1), [7; 4]);
+        assert_eq!(pixel(&composed, 5, 2), [0; 4]);
+    }
+}
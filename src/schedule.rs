@@ -0,0 +1,109 @@
+use std::time::{Duration, Instant, SystemTime};
+
+/// When the frames of [`crate::Screenshot::capture_series`] are taken.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    /// Stop after this many frames.
+    pub count: Option<usize>,
+    /// The time from the start of one frame to the start of the next.
+    pub interval: Duration,
+    /// Stop once this long has passed since the first frame.
+    pub until: Option<Duration>,
+}
+
+impl Schedule {
+    /// A single frame.
+    pub fn once() -> Self {
+        Self {
+            count: Some(1),
+            interval: Duration::ZERO,
+            until: None,
+        }
+    }
+
+    /// How long after the first frame the frame at `index` is due, or `None`
+    /// once the series is over. The first frame is always taken.
+    pub fn due(&self, index: usize) -> Option<Duration> {
+        if self.count.is_some_and(|count| index >= count) {
+            return None;
+        }
+        let due = self.interval.checked_mul(u32::try_from(index).ok()?)?;
+        match self.until {
+            Some(until) if index > 0 && due >= until => None,
+            _ => Some(due),
+        }
+    }
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self::once()
+    }
+}
+
+/// Tells the time and waits, so a series of captures can be tested without waiting.
+pub trait Clock {
+    fn now(&self) -> SystemTime;
+    fn sleep(&self, duration: Duration);
+}
+
+/// The system clock. Time is measured from when it was created, so changes to
+/// the system time don't upset the schedule.
+#[derive(Copy, Clone, Debug)]
+pub struct SystemClock {
+    started: Instant,
+    start_time: SystemTime,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            start_time: SystemTime::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        self.start_time + self.started.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn due_times(schedule: Schedule) -> Vec<u64> {
+        (0..10)
+            .map_while(|index| schedule.due(index))
+            .map(|due| due.as_millis() as u64)
+            .collect()
+    }
+
+    #[test]
+    fn schedules_frames() {
+        let interval = Duration::from_millis(500);
+        let schedule = |count, until: Option<u64>| Schedule {
+            count,
+            interval,
+            until: until.map(Duration::from_millis),
+        };
+        assert_eq!(due_times(Schedule::once()), [0]);
+        assert_eq!(due_times(schedule(Some(3), None)), [0, 500, 1000]);
+        // Frames due at or after the end of the series aren't taken
+        assert_eq!(due_times(schedule(None, Some(2000))), [0, 500, 1000, 1500]);
+        assert_eq!(due_times(schedule(Some(2), Some(2000))), [0, 500]);
+        assert_eq!(due_times(schedule(None, Some(0))), [0]);
+    }
+}
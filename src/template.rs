@@ -0,0 +1,404 @@
+use crate::frame::Frame;
+use crate::metadata::{filename_timestamp, format_timestamp, Metadata};
+use crate::{Result, ScreenshotError};
+use std::fs::{File, OpenOptions};
+use std::io::ErrorKind;
+use std::str::FromStr;
+
+/// An output path with placeholders that are filled in for every capture, like
+/// `shot-{n:04}-{timestamp}.png`.
+///
+/// | Placeholder   | Replaced with                                                |
+/// |---------------|--------------------------------------------------------------|
+/// | `{n}`         | The number of the capture in a series, starting at 1         |
+/// | `{n:04}`      | The number, padded with zeros to four digits                 |
+/// | `{timestamp}` | The capture time, as written by [`filename_timestamp`]       |
+/// | `{date}`      | The UTC capture date, e.g. `2024-05-01`                      |
+/// | `{time}`      | The UTC capture time, e.g. `13-37-00`                        |
+/// | `{title}`     | The title of the captured window                             |
+/// | `{process}`   | The executable owning the captured window, without `.exe`    |
+/// | `{monitor}`   | The id of the captured monitor                               |
+/// | `{width}`     | The width of the image                                       |
+/// | `{height}`    | The height of the image                                      |
+///
+/// Placeholders that don't apply to a capture, like `{title}` of a monitor, are
+/// left empty. Titles and process names are made safe to use as file names.
+/// `{{` and `}}` stand for `{` and `}`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilenameTemplate {
+    template: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Number { width: usize },
+    Timestamp,
+    Date,
+    Time,
+    Title,
+    Process,
+    Monitor,
+    Width,
+    Height,
+}
+
+impl FilenameTemplate {
+    /// The template as it was written.
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    /// Whether every capture of a series gets a different path, as long as they
+    /// aren't taken in the same millisecond.
+    pub fn varies_per_capture(&self) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, Part::Number { .. } | Part::Timestamp))
+    }
+
+    /// The path for the `number`th capture, taken as described by `metadata`.
+    pub fn render(&self, number: usize, frame: &Frame, metadata: &Metadata) -> String {
+        let timestamp = format_timestamp(metadata.capture_time);
+        let mut path = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => path.push_str(text),
+                Part::Number { width } => path.push_str(&format!("{:0width$}", number)),
+                Part::Timestamp => path.push_str(&filename_timestamp(metadata.capture_time)),
+                Part::Date => path.push_str(&timestamp[..10]),
+                Part::Time => path.push_str(&timestamp[11..19].replace(':', "-")),
+                Part::Title => {
+                    if let Some(title) = &metadata.window_title {
+                        path.push_str(&sanitize_file_name(title));
+                    }
+                }
+                Part::Process => {
+                    if let Some(process_name) = &metadata.process_name {
+                        path.push_str(&sanitize_file_name(strip_exe(process_name)));
+                    }
+                }
+                Part::Monitor => {
+                    if let Some(monitor) = metadata.monitor {
+                        path.push_str(&monitor.to_string());
+                    }
+                }
+                Part::Width => path.push_str(&frame.width.to_string()),
+                Part::Height => path.push_str(&frame.height.to_string()),
+            }
+        }
+        path
+    }
+}
+
+impl FromStr for FilenameTemplate {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = input.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.next_if_eq(&'{').is_some() => text.push('{'),
+                '}' if chars.next_if_eq(&'}').is_some() => text.push('}'),
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => {
+                                return Err(format!(
+                                    "'{}' has a '{{' without a matching '}}'",
+                                    input
+                                ))
+                            }
+                        }
+                    }
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(parse_placeholder(&placeholder)?);
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Self {
+            template: input.to_owned(),
+            parts,
+        })
+    }
+}
+
+fn parse_placeholder(placeholder: &str) -> std::result::Result<Part, String> {
+    let (name, spec) = match placeholder.split_once(':') {
+        Some((name, spec)) => (name, Some(spec)),
+        None => (placeholder, None),
+    };
+    match (name, spec) {
+        ("n", None) => Ok(Part::Number { width: 0 }),
+        ("n", Some(spec)) => match spec.strip_prefix('0').map(str::parse) {
+            Some(Ok(width)) => Ok(Part::Number { width }),
+            _ => Err(format!(
+                "'{{{}}}' isn't a number format, expecting e.g. {{n:04}}",
+                placeholder
+            )),
+        },
+        ("timestamp", None) => Ok(Part::Timestamp),
+        ("date", None) => Ok(Part::Date),
+        ("time", None) => Ok(Part::Time),
+        ("title", None) => Ok(Part::Title),
+        ("process", None) => Ok(Part::Process),
+        ("monitor", None) => Ok(Part::Monitor),
+        ("width", None) => Ok(Part::Width),
+        ("height", None) => Ok(Part::Height),
+        _ => Err(format!(
+            "'{{{}}}' isn't a placeholder, expecting {{n}}, {{timestamp}}, {{date}}, {{time}}, \
+             {{title}}, {{process}}, {{monitor}}, {{width}} or {{height}}",
+            placeholder
+        )),
+    }
+}
+
+/// The longest a title is allowed to get in a file name, in characters.
+const MAX_NAME_LENGTH: usize = 100;
+
+/// Makes `name` safe to use as (part of) a file name on Windows, replacing
+/// characters it doesn't allow and avoiding the names of devices.
+pub fn sanitize_file_name(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .take(MAX_NAME_LENGTH)
+        .collect();
+    // Windows drops trailing dots and spaces
+    sanitized.truncate(sanitized.trim_end_matches(['.', ' ']).len());
+    let is_device = |name: &str| {
+        let name = name.to_ascii_uppercase();
+        matches!(name.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+            || (name.len() == 4
+                && (name.starts_with("COM") || name.starts_with("LPT"))
+                && matches!(name.as_bytes()[3], b'1'..=b'9'))
+    };
+    if is_device(&sanitized) {
+        sanitized.push('_');
+    }
+    sanitized
+}
+
+fn strip_exe(process_name: &str) -> &str {
+    match process_name.len().checked_sub(4) {
+        Some(end)
+            if process_name.is_char_boundary(end)
+                && process_name[end..].eq_ignore_ascii_case(".exe") =>
+        {
+            &process_name[..end]
+        }
+        _ => process_name,
+    }
+}
+
+/// What to do when the output file already exists.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Collision {
+    /// Replace the existing file.
+    #[default]
+    Overwrite,
+    /// Fail instead of replacing it.
+    NoClobber,
+    /// Add `-1`, `-2`, ... to the file name until it's one that doesn't exist.
+    Unique,
+}
+
+impl Collision {
+    /// Creates the file at `path`, or at the path this policy picks instead.
+    /// Existing files are only ever replaced by [`Collision::Overwrite`]; the
+    /// others create the file exclusively, so one appearing in the meantime
+    /// isn't overwritten either.
+    pub fn create_file(self, path: &str) -> Result<File> {
+        self.open(path, |path, create_new| {
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .create_new(create_new)
+                .open(path)
+        })
+    }
+
+    /// Calls `open` with the path to create and whether it must not exist yet,
+    /// trying the next name when [`Collision::Unique`] finds one taken.
+    pub fn open<T, F>(self, path: &str, mut open: F) -> Result<T>
+    where
+        F: FnMut(&str, bool) -> std::io::Result<T>,
+    {
+        match self {
+            Collision::Overwrite => Ok(open(path, false)?),
+            Collision::NoClobber => open(path, true).map_err(|error| {
+                if error.kind() == ErrorKind::AlreadyExists {
+                    ScreenshotError::Io(std::io::Error::new(
+                        ErrorKind::AlreadyExists,
+                        format!("'{}' already exists!", path),
+                    ))
+                } else {
+                    ScreenshotError::Io(error)
+                }
+            }),
+            Collision::Unique => {
+                let mut number = 0;
+                loop {
+                    let candidate = match number {
+                        0 => path.to_owned(),
+                        number => numbered(path, number),
+                    };
+                    match open(&candidate, true) {
+                        Err(error) if error.kind() == ErrorKind::AlreadyExists => number += 1,
+                        result => return Ok(result?),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Adds `-number` to the file name of `path`, before its extension.
+fn numbered(path: &str, number: usize) -> String {
+    let name_start = path.rfind(['/', '\\']).map_or(0, |index| index + 1);
+    match path[name_start..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let (stem, extension) = path.split_at(name_start + dot);
+            format!("{}-{}{}", stem, number, extension)
+        }
+        _ => format!("{}-{}", path, number),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::PixelFormat;
+    use crate::mock::test_pattern;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn render(template: &str, number: usize) -> String {
+        let mut metadata = Metadata::now();
+        metadata.capture_time = UNIX_EPOCH + Duration::from_millis(1_714_570_620_250);
+        metadata.window_title = Some("Report: Q1/Q2 *draft*".to_owned());
+        metadata.process_name = Some("WINWORD.EXE".to_owned());
+        template.parse::<FilenameTemplate>().unwrap().render(
+            number,
+            &test_pattern(64, 32, PixelFormat::Bgra8),
+            &metadata,
+        )
+    }
+
+    #[test]
+    fn fills_in_placeholders() {
+        assert_eq!(
+            render("shot-{n:04}-{timestamp}.png", 7),
+            "shot-0007-2024-05-01T13-37-00.250Z.png"
+        );
+        assert_eq!(render("{n}.png", 12), "12.png");
+        assert_eq!(render("{n:02}.png", 123), "123.png");
+        assert_eq!(render("{{n}}-{n}.png", 3), "{n}-3.png");
+        assert_eq!(render("screenshot.png", 3), "screenshot.png");
+        assert_eq!(
+            render("shots/{date}_{time}.png", 1),
+            "shots/2024-05-01_13-37-00.png"
+        );
+        assert_eq!(
+            render("{process}-{title}-{width}x{height}.png", 1),
+            "WINWORD-Report_ Q1_Q2 _draft_-64x32.png"
+        );
+        // Only monitor captures have a monitor
+        assert_eq!(render("monitor{monitor}.png", 1), "monitor.png");
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders() {
+        let parse = |template: &str| template.parse::<FilenameTemplate>();
+        assert!(parse("{name}.png").is_err());
+        assert!(parse("{n:4}.png").is_err());
+        assert!(parse("{n.png").is_err());
+        assert!(!parse("shot.png").unwrap().varies_per_capture());
+        assert!(!parse("{title}.png").unwrap().varies_per_capture());
+        assert!(parse("shot-{n}.png").unwrap().varies_per_capture());
+    }
+
+    #[test]
+    fn sanitizes_file_names() {
+        assert_eq!(
+            sanitize_file_name("a<b>c:d\"e/f\\g|h?i*j"),
+            "a_b_c_d_e_f_g_h_i_j"
+        );
+        assert_eq!(sanitize_file_name("tab\there"), "tab_here");
+        assert_eq!(sanitize_file_name("Loading... "), "Loading");
+        assert_eq!(sanitize_file_name("con"), "con_");
+        assert_eq!(sanitize_file_name("COM1"), "COM1_");
+        assert_eq!(sanitize_file_name("Console"), "Console");
+        assert_eq!(sanitize_file_name(&"x".repeat(300)).len(), MAX_NAME_LENGTH);
+        assert_eq!(strip_exe("notepad.exe"), "notepad");
+        assert_eq!(strip_exe("exe"), "exe");
+    }
+
+    #[test]
+    fn resolves_collisions() {
+        let existing = ["shot.png", "shot-1.png", "dir.v2/shot"];
+        let resolve = |collision: Collision, path| {
+            collision.open(path, |path, create_new| {
+                if create_new && existing.contains(&path) {
+                    Err(std::io::Error::from(ErrorKind::AlreadyExists))
+                } else {
+                    Ok(path.to_owned())
+                }
+            })
+        };
+        assert_eq!(
+            resolve(Collision::Overwrite, "shot.png").unwrap(),
+            "shot.png"
+        );
+        assert_eq!(resolve(Collision::NoClobber, "new.png").unwrap(), "new.png");
+        assert_eq!(
+            resolve(Collision::NoClobber, "shot.png")
+                .unwrap_err()
+                .exit_code(),
+            9
+        );
+        assert_eq!(
+            resolve(Collision::Unique, "shot.png").unwrap(),
+            "shot-2.png"
+        );
+        assert_eq!(
+            resolve(Collision::Unique, "dir.v2/shot").unwrap(),
+            "dir.v2/shot-1"
+        );
+    }
+
+    #[test]
+    fn creates_files_without_replacing_them() {
+        let dir = std::env::temp_dir().join(format!("screenshot-collision-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("shot.png");
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "old").unwrap();
+
+        let error = Collision::NoClobber.create_file(path).unwrap_err();
+        assert_eq!(error.exit_code(), 9);
+        Collision::Unique.create_file(path).unwrap();
+        assert!(dir.join("shot-1.png").exists());
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "old");
+        Collision::Overwrite.create_file(path).unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
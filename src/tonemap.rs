@@ -0,0 +1,320 @@
+use crate::frame::{Frame, PixelFormat};
+use std::str::FromStr;
+
+/// scRGB defines 1.0 as 80 nits.
+pub const SCRGB_WHITE_NITS: f32 = 80.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    /// Clamp everything brighter than SDR white.
+    Clip,
+    /// Extended Reinhard on luminance, with the brightest pixel mapped to SDR white.
+    Reinhard,
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve, applied per channel.
+    AcesFilmic,
+    /// The ITU-R BT.2390 EETF on PQ-encoded luminance.
+    Bt2390,
+}
+
+impl FromStr for ToneMapOperator {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "clip" => Ok(ToneMapOperator::Clip),
+            "reinhard" => Ok(ToneMapOperator::Reinhard),
+            "aces" => Ok(ToneMapOperator::AcesFilmic),
+            "bt2390" => Ok(ToneMapOperator::Bt2390),
+            _ => Err(format!(
+                "'{}' isn't a tone mapping operator, expecting clip, reinhard, aces or bt2390",
+                input
+            )),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ToneMapOptions {
+    pub operator: ToneMapOperator,
+    /// The luminance, in nits, that becomes white in the SDR image.
+    pub sdr_white_nits: f32,
+}
+
+impl Default for ToneMapOptions {
+    fn default() -> Self {
+        Self {
+            operator: ToneMapOperator::Clip,
+            sdr_white_nits: SCRGB_WHITE_NITS,
+        }
+    }
+}
+
+/// Converts an FP16 scRGB frame into a BGRA8 sRGB frame.
+pub fn tone_map(frame: &Frame, options: &ToneMapOptions) -> Frame {
+    assert_eq!(frame.format, PixelFormat::Rgba16Float);
+
+    // Work relative to SDR white, so 1.0 is the brightest SDR value.
+    let scale = SCRGB_WHITE_NITS / options.sdr_white_nits;
+    let pixels: Vec<[f32; 4]> = frame
+        .rgba16f_pixels()
+        .map(|[r, g, b, a]| {
+            [
+                r.to_f32() * scale,
+                g.to_f32() * scale,
+                b.to_f32() * scale,
+                a.to_f32(),
+            ]
+        })
+        .collect();
+    let peak = pixels
+        .iter()
+        .map(|[r, g, b, _]| luminance(*r, *g, *b))
+        .fold(1.0f32, f32::max);
+
+    let mut bytes = Vec::with_capacity(pixels.len() * 4);
+    for [r, g, b, a] in pixels {
+        let [r, g, b] = match options.operator {
+            ToneMapOperator::Clip => [r, g, b],
+            ToneMapOperator::Reinhard => {
+                scale_luminance([r, g, b], |y| y * (1.0 + y / (peak * peak)) / (1.0 + y))
+            }
+            ToneMapOperator::AcesFilmic => [aces_filmic(r), aces_filmic(g), aces_filmic(b)],
+            ToneMapOperator::Bt2390 => scale_luminance([r, g, b], |y| {
+                let nits = bt2390_eetf(
+                    y * options.sdr_white_nits,
+                    peak * options.sdr_white_nits,
+                    options.sdr_white_nits,
+                );
+                nits / options.sdr_white_nits
+            }),
+        };
+        bytes.extend_from_slice(&[
+            linear_to_srgb8(b),
+            linear_to_srgb8(g),
+            linear_to_srgb8(r),
+            (a.clamp(0.0, 1.0) * 255.0).round() as u8,
+        ]);
+    }
+    Frame::new(frame.width, frame.height, PixelFormat::Bgra8, bytes)
+}
+
+/// BT.709 luminance, which shares its primaries with scRGB.
+fn luminance(r: f32, g: f32, b: f32) -> f32 {
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Maps the luminance of a color while keeping its hue.
+fn scale_luminance(rgb: [f32; 3], map: impl Fn(f32) -> f32) -> [f32; 3] {
+    let [r, g, b] = rgb;
+    let y = luminance(r, g, b);
+    if y <= 0.0 {
+        return rgb;
+    }
+    let ratio = map(y) / y;
+    [r * ratio, g * ratio, b * ratio]
+}
+
+fn aces_filmic(x: f32) -> f32 {
+    let x = x.max(0.0);
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+/// Compresses `nits` from a source that peaks at `source_peak` into a display
+/// that peaks at `target_peak`, following BT.2390 section 5.4 with a black level of 0.
+fn bt2390_eetf(nits: f32, source_peak: f32, target_peak: f32) -> f32 {
+    if source_peak <= target_peak {
+        return nits;
+    }
+    let source_max = pq_encode(source_peak);
+    let e1 = pq_encode(nits.max(0.0)) / source_max;
+    let max_luminance = pq_encode(target_peak) / source_max;
+    let knee_start = 1.5 * max_luminance - 0.5;
+    let e2 = if e1 < knee_start {
+        e1
+    } else {
+        let t = (e1 - knee_start) / (1.0 - knee_start);
+        let (t2, t3) = (t * t, t * t * t);
+        (2.0 * t3 - 3.0 * t2 + 1.0) * knee_start
+            + (t3 - 2.0 * t2 + t) * (1.0 - knee_start)
+            + (-2.0 * t3 + 3.0 * t2) * max_luminance
+    };
+    pq_decode(e2 * source_max)
+}
+
+/// Converts linear BT.709 (and scRGB) color to linear BT.2020, per BT.2087.
+pub fn bt709_to_bt2020([r, g, b]: [f32; 3]) -> [f32; 3] {
+    [
+        0.627_404 * r + 0.329_282 * g + 0.043_314 * b,
+        0.069_097 * r + 0.919_540 * g + 0.011_363 * b,
+        0.016_392 * r + 0.088_013 * g + 0.895_595 * b,
+    ]
+}
+
+const PQ_M1: f32 = 2610.0 / 16384.0;
+const PQ_M2: f32 = 2523.0 / 4096.0 * 128.0;
+const PQ_C1: f32 = 3424.0 / 4096.0;
+const PQ_C2: f32 = 2413.0 / 4096.0 * 32.0;
+const PQ_C3: f32 = 2392.0 / 4096.0 * 32.0;
+
+/// The SMPTE ST 2084 (PQ) inverse EOTF, from nits to a `0..=1` signal.
+pub fn pq_encode(nits: f32) -> f32 {
+    let y = (nits / 10_000.0).clamp(0.0, 1.0).powf(PQ_M1);
+    ((PQ_C1 + PQ_C2 * y) / (1.0 + PQ_C3 * y)).powf(PQ_M2)
+}
+
+/// The SMPTE ST 2084 (PQ) EOTF, from a `0..=1` signal to nits.
+pub fn pq_decode(signal: f32) -> f32 {
+    let e = signal.clamp(0.0, 1.0).powf(1.0 / PQ_M2);
+    let y = ((e - PQ_C1).max(0.0) / (PQ_C2 - PQ_C3 * e)).powf(1.0 / PQ_M1);
+    y * 10_000.0
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+    let value = value.clamp(0.0, 1.0);
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.040_45 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb8(value: f32) -> u8 {
+    (linear_to_srgb(value) * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use half::f16;
+
+    const OPERATORS: [ToneMapOperator; 4] = [
+        ToneMapOperator::Clip,
+        ToneMapOperator::Reinhard,
+        ToneMapOperator::AcesFilmic,
+        ToneMapOperator::Bt2390,
+    ];
+
+    /// Tone maps a row of gray pixels and returns their 8-bit values.
+    fn tone_map_grays(operator: ToneMapOperator, sdr_white_nits: f32, grays: &[f32]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for gray in grays {
+            for channel in [*gray, *gray, *gray, 1.0] {
+                bytes.extend_from_slice(&f16::from_f32(channel).to_le_bytes());
+            }
+        }
+        let frame = Frame::new(grays.len() as u32, 1, PixelFormat::Rgba16Float, bytes);
+        let options = ToneMapOptions {
+            operator,
+            sdr_white_nits,
+        };
+        let frame = tone_map(&frame, &options);
+        assert!(frame.bytes.chunks_exact(4).all(|bgra| bgra[0] == bgra[2]));
+        frame.bytes.chunks_exact(4).map(|bgra| bgra[2]).collect()
+    }
+
+    #[test]
+    fn maps_sdr_white_to_white() {
+        for operator in [
+            ToneMapOperator::Clip,
+            ToneMapOperator::Reinhard,
+            ToneMapOperator::Bt2390,
+        ] {
+            assert_eq!(tone_map_grays(operator, 80.0, &[0.0, 1.0]), [0, 255]);
+            // 160 nits is scRGB 2.0
+            assert_eq!(tone_map_grays(operator, 160.0, &[2.0]), [255]);
+        }
+        // The ACES curve rolls off before white
+        let aces = tone_map_grays(ToneMapOperator::AcesFilmic, 80.0, &[1.0]);
+        assert!((220..240).contains(&aces[0]));
+    }
+
+    #[test]
+    fn curves_are_monotonic() {
+        let ramp: Vec<f32> = (0..=80).map(|step| step as f32 / 4.0).collect();
+        for operator in OPERATORS {
+            let values = tone_map_grays(operator, 80.0, &ramp);
+            assert!(
+                values.windows(2).all(|pair| pair[0] <= pair[1]),
+                "{:?} isn't monotonic: {:?}",
+                operator,
+                values
+            );
+        }
+    }
+
+    #[test]
+    fn saturates_above_peak() {
+        assert_eq!(
+            tone_map_grays(ToneMapOperator::Clip, 80.0, &[2.0, 12.5]),
+            [255, 255]
+        );
+        assert_eq!(
+            tone_map_grays(ToneMapOperator::AcesFilmic, 80.0, &[10.0, 20.0]),
+            [255, 255]
+        );
+        // Reinhard and BT.2390 map the brightest pixel to white, and
+        // compress SDR white below it
+        for operator in [ToneMapOperator::Reinhard, ToneMapOperator::Bt2390] {
+            let values = tone_map_grays(operator, 80.0, &[1.0, 4.0, 12.5]);
+            assert!(values[0] < 255, "{:?}: {:?}", operator, values);
+            assert_eq!(values[2], 255);
+        }
+    }
+
+    #[test]
+    fn compresses_with_bt2390_eetf() {
+        // Below the knee, luminance is left alone
+        assert!((bt2390_eetf(10.0, 1000.0, 80.0) - 10.0).abs() < 0.01);
+        assert!((bt2390_eetf(1000.0, 1000.0, 80.0) - 80.0).abs() < 0.01);
+        assert_eq!(bt2390_eetf(500.0, 80.0, 80.0), 500.0);
+    }
+
+    #[test]
+    fn pq_round_trips() {
+        assert_eq!(pq_encode(0.0), PQ_C1.powf(PQ_M2));
+        assert!((pq_encode(10_000.0) - 1.0).abs() < 1e-6);
+        assert!((pq_encode(100.0) - 0.508).abs() < 0.001);
+        assert!((pq_encode(1000.0) - 0.752).abs() < 0.001);
+        assert_eq!(pq_encode(20_000.0), pq_encode(10_000.0));
+        for nits in [0.1, 1.0, 80.0, 203.0, 1000.0, 4000.0, 10_000.0] {
+            let decoded = pq_decode(pq_encode(nits));
+            assert!(
+                (decoded - nits).abs() / nits < 1e-3,
+                "{} nits came back as {}",
+                nits,
+                decoded
+            );
+        }
+    }
+
+    #[test]
+    fn converts_bt709_to_bt2020() {
+        let [r, g, b] = bt709_to_bt2020([1.0, 1.0, 1.0]);
+        assert!([r, g, b].iter().all(|value| (value - 1.0).abs() < 1e-5));
+        assert_eq!(
+            bt709_to_bt2020([1.0, 0.0, 0.0]),
+            [0.627_404, 0.069_097, 0.016_392]
+        );
+    }
+
+    #[test]
+    fn converts_srgb() {
+        assert_eq!(linear_to_srgb(0.0), 0.0);
+        assert!((linear_to_srgb(1.0) - 1.0).abs() < 1e-6);
+        assert!((linear_to_srgb(0.18) - 0.4614).abs() < 1e-4);
+        assert_eq!(linear_to_srgb(2.0), linear_to_srgb(1.0));
+        assert!((srgb_to_linear(0.5) - 0.2140).abs() < 1e-4);
+        for value in [0.001, 0.02, 0.5, 0.9] {
+            assert!((srgb_to_linear(linear_to_srgb(value)) - value).abs() < 1e-5);
+        }
+        assert_eq!(linear_to_srgb8(0.5), 188);
+    }
+}
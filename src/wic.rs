@@ -1,8 +1,26 @@
+use crate::encode::{srgb_icc_profile, ColorProfile, EncodeOptions, ImageFormat};
+use crate::frame::{Frame, PixelFormat};
+use crate::metadata::Metadata;
+use crate::{Result, ScreenshotError};
+use std::io::Write;
+use std::mem::ManuallyDrop;
 use windows::{
-    core::Result,
+    core::{HSTRING, PSTR, PWSTR},
     Win32::{
-        Graphics::Imaging::{CLSID_WICImagingFactory, IWICImagingFactory},
-        System::Com::{CoCreateInstance, CLSCTX_INPROC_SERVER},
+        Foundation::E_OUTOFMEMORY,
+        Graphics::Imaging::{
+            CLSID_WICImagingFactory, GUID_ContainerFormatPng, GUID_ContainerFormatWmp,
+            GUID_WICPixelFormat32bppBGRA, GUID_WICPixelFormat64bppRGBAHalf, IWICImagingFactory,
+            IWICMetadataQueryWriter, WICBitmapEncoderNoCache,
+        },
+        System::Com::StructuredStorage::{
+            PropVariantClear, PROPVARIANT, PROPVARIANT_0, PROPVARIANT_0_0, PROPVARIANT_0_0_0,
+        },
+        System::Com::{
+            CoCreateInstance, CoTaskMemAlloc, IStream, CLSCTX_INPROC_SERVER, STREAM_SEEK_SET,
+        },
+        System::Variant::{VARENUM, VT_LPSTR, VT_LPWSTR},
+        UI::Shell::SHCreateMemStream,
     },
 };
 
@@ -11,3 +29,206 @@ pub fn create_wic_factory() -> Result<IWICImagingFactory> {
         unsafe { CoCreateInstance(&CLSID_WICImagingFactory, None, CLSCTX_INPROC_SERVER)? };
     Ok(wic_factory)
 }
+
+/// Encodes `frame` into memory with WIC, then copies the image into `writer`.
+pub fn encode_frame<W: Write>(
+    wic_factory: &IWICImagingFactory,
+    frame: &Frame,
+    format: ImageFormat,
+    options: &EncodeOptions,
+    writer: W,
+) -> Result<()> {
+    let (container_format, pixel_format) = match (format, frame.format) {
+        (ImageFormat::Png, PixelFormat::Bgra8) => {
+            (GUID_ContainerFormatPng, GUID_WICPixelFormat32bppBGRA)
+        }
+        (ImageFormat::Jxr, PixelFormat::Rgba16Float) => {
+            (GUID_ContainerFormatWmp, GUID_WICPixelFormat64bppRGBAHalf)
+        }
+        (ImageFormat::Jpeg | ImageFormat::WebP | ImageFormat::Exr | ImageFormat::Hdr, _) => {
+            return Err(ScreenshotError::UnsupportedFormat(format!(
+                "{:?} can only be written by the native encoder!",
+                format
+            )))
+        }
+        _ => {
+            return Err(ScreenshotError::UnsupportedFormat(format!(
+                "{:?} can't hold {:?} pixels!",
+                format, frame.format
+            )))
+        }
+    };
+
+    let encoder = unsafe { wic_factory.CreateEncoder(&container_format, std::ptr::null())? };
+
+    unsafe {
+        let stream =
+            SHCreateMemStream(None).ok_or_else(|| windows::core::Error::from(E_OUTOFMEMORY))?;
+        encoder.Initialize(&stream, WICBitmapEncoderNoCache)?;
+        let (wic_frame, props) = {
+            let mut wic_frame = None;
+            let mut props = None;
+            encoder.CreateNewFrame(&mut wic_frame, &mut props)?;
+            (wic_frame.unwrap(), props.unwrap())
+        };
+
+        wic_frame.Initialize(&props)?;
+        wic_frame.SetSize(frame.width, frame.height)?;
+        let mut target_format = pixel_format;
+        wic_frame.SetPixelFormat(&mut target_format)?;
+        if target_format != pixel_format {
+            return Err(ScreenshotError::Encode(
+                "Unsupported WIC pixel format!".to_owned(),
+            ));
+        }
+
+        if format == ImageFormat::Png {
+            let color_context = match options.png.color_profile {
+                ColorProfile::None => None,
+                ColorProfile::Srgb => {
+                    let color_context = wic_factory.CreateColorContext()?;
+                    // EXIF color space 1 is sRGB
+                    color_context.InitializeFromExifColorSpace(1)?;
+                    Some(color_context)
+                }
+                ColorProfile::Icc => {
+                    let color_context = wic_factory.CreateColorContext()?;
+                    color_context.InitializeFromMemory(&srgb_icc_profile())?;
+                    Some(color_context)
+                }
+            };
+            if color_context.is_some() {
+                wic_frame.SetColorContexts(&[color_context])?;
+            }
+        }
+
+        if let Some(metadata) = options
+            .metadata
+            .as_ref()
+            .filter(|_| format == ImageFormat::Png)
+        {
+            if let Some(dpi) = metadata.dpi {
+                wic_frame.SetResolution(dpi as f64, dpi as f64)?;
+            }
+            write_png_text(&wic_frame.GetMetadataQueryWriter()?, metadata)?;
+        }
+
+        wic_frame.WritePixels(frame.height, frame.stride(), &frame.bytes)?;
+        wic_frame.Commit()?;
+        encoder.Commit()?;
+
+        copy_stream(&stream, writer)
+    }
+}
+
+/// Copies everything written to `stream` into `writer`.
+fn copy_stream<W: Write>(stream: &IStream, mut writer: W) -> Result<()> {
+    let mut buffer = vec![0u8; 64 * 1024];
+    unsafe {
+        stream.Seek(0, STREAM_SEEK_SET, None)?;
+        loop {
+            let mut read = 0;
+            stream
+                .Read(
+                    buffer.as_mut_ptr() as *mut _,
+                    buffer.len() as u32,
+                    Some(&mut read),
+                )
+                .ok()?;
+            if read == 0 {
+                break;
+            }
+            writer.write_all(&buffer[..read as usize])?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the metadata text entries as tEXt chunks. WIC can only address a single
+/// iTXt chunk, so the first entry that doesn't fit in Latin-1 goes there and any
+/// others are written as tEXt with the offending characters replaced.
+fn write_png_text(writer: &IWICMetadataQueryWriter, metadata: &Metadata) -> Result<()> {
+    let mut itxt_written = false;
+    for (keyword, text) in metadata.text_entries() {
+        let is_latin1 = text.chars().all(|c| (c as u32) < 0x100);
+        if !is_latin1 && !itxt_written {
+            set_metadata(writer, "/iTXt/Keyword", lpstr(keyword)?)?;
+            set_metadata(writer, "/iTXt/TextEntry", lpwstr(&text)?)?;
+            itxt_written = true;
+        } else {
+            let name = format!("/tEXt/{{str={}}}", keyword);
+            set_metadata(writer, &name, lpstr(&text)?)?;
+        }
+    }
+    Ok(())
+}
+
+/// Sets the metadata at `name`, then frees the string `value` owns, whether or not
+/// that worked. The writer keeps its own copy.
+fn set_metadata(
+    writer: &IWICMetadataQueryWriter,
+    name: &str,
+    mut value: PROPVARIANT,
+) -> Result<()> {
+    unsafe {
+        let result = writer.SetMetadataByName(&HSTRING::from(name), &value);
+        PropVariantClear(&mut value)?;
+        result?;
+    }
+    Ok(())
+}
+
+/// A `VT_LPSTR` holding `text` as Latin-1, which is what tEXt chunks store.
+fn lpstr(text: &str) -> Result<PROPVARIANT> {
+    let units: Vec<u8> = text
+        .chars()
+        .map(|c| u8::try_from(c as u32).unwrap_or(b'?'))
+        .chain([0])
+        .collect();
+    let buffer = co_task_copy(&units)?;
+    Ok(string_propvariant(
+        VT_LPSTR,
+        PROPVARIANT_0_0_0 {
+            pszVal: PSTR(buffer),
+        },
+    ))
+}
+
+/// A `VT_LPWSTR` holding `text` as UTF-16.
+fn lpwstr(text: &str) -> Result<PROPVARIANT> {
+    let units: Vec<u16> = text.encode_utf16().chain([0]).collect();
+    let buffer = co_task_copy(&units)?;
+    Ok(string_propvariant(
+        VT_LPWSTR,
+        PROPVARIANT_0_0_0 {
+            pwszVal: PWSTR(buffer),
+        },
+    ))
+}
+
+/// Copies `units` into memory owned by the COM allocator. `PROPVARIANT` has no `Drop`,
+/// so a variant holding the copy has to be freed with `PropVariantClear`.
+fn co_task_copy<T: Copy>(units: &[T]) -> Result<*mut T> {
+    unsafe {
+        let buffer = CoTaskMemAlloc(std::mem::size_of_val(units)) as *mut T;
+        if buffer.is_null() {
+            return Err(windows::core::Error::from(E_OUTOFMEMORY).into());
+        }
+        std::ptr::copy_nonoverlapping(units.as_ptr(), buffer, units.len());
+        Ok(buffer)
+    }
+}
+
+fn string_propvariant(vt: VARENUM, value: PROPVARIANT_0_0_0) -> PROPVARIANT {
+    PROPVARIANT {
+        Anonymous: PROPVARIANT_0 {
+            Anonymous: ManuallyDrop::new(PROPVARIANT_0_0 {
+                vt,
+                wReserved1: 0,
+                wReserved2: 0,
+                wReserved3: 0,
+                Anonymous: value,
+            }),
+        },
+    }
+}
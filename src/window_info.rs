@@ -1,42 +1,273 @@
-use windows::Win32::Foundation::HWND;
-use windows::Win32::UI::WindowsAndMessaging::{GetClassNameW, GetWindowTextW};
+use crate::display_info::DisplayInfo;
+use crate::matcher::{MatchMode, TitleMatcher};
+use crate::region::Rect;
+use std::fmt;
+use std::str::FromStr;
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct WindowInfo {
-    pub handle: HWND,
+    /// The raw `HWND` of the window.
+    pub handle: isize,
     pub title: String,
     pub class_name: String,
+    /// The id of the process that owns the window.
+    pub pid: u32,
+    /// The file name of the owning process' executable, e.g. `notepad.exe`, if
+    /// it could be queried.
+    pub process_name: Option<String>,
+    /// Where the window sits on the virtual desktop, without its drop shadow.
+    pub rect: Rect,
 }
 
-impl WindowInfo {
-    // TODO: Return result?
-    pub fn new(window_handle: HWND) -> Self {
+#[cfg(windows)]
+mod platform {
+    use super::WindowInfo;
+    use crate::region::Rect;
+    use windows::core::PWSTR;
+    use windows::Win32::Foundation::{CloseHandle, HWND, RECT};
+    use windows::Win32::Graphics::Dwm::{DwmGetWindowAttribute, DWMWA_EXTENDED_FRAME_BOUNDS};
+    use windows::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{
+        GetClassNameW, GetWindowRect, GetWindowTextW, GetWindowThreadProcessId,
+    };
+
+    impl WindowInfo {
+        // TODO: Return result?
+        pub fn new(window_handle: HWND) -> Self {
+            unsafe {
+                let mut title = [0u16; 512];
+                GetWindowTextW(window_handle, &mut title);
+                let mut title = String::from_utf16_lossy(&title);
+                truncate_to_first_null_char(&mut title);
+
+                let mut class_name = [0u16; 512];
+                GetClassNameW(window_handle, &mut class_name);
+                let mut class_name = String::from_utf16_lossy(&class_name);
+                truncate_to_first_null_char(&mut class_name);
+
+                let mut pid = 0;
+                GetWindowThreadProcessId(window_handle, Some(&mut pid));
+
+                Self {
+                    handle: window_handle.0 as isize,
+                    title,
+                    class_name,
+                    pid,
+                    process_name: process_name(pid),
+                    rect: window_rect(window_handle),
+                }
+            }
+        }
+
+        pub fn hwnd(&self) -> HWND {
+            HWND(self.handle as *mut _)
+        }
+    }
+
+    /// The file name of a process' executable. Fails for elevated processes
+    /// when we aren't elevated ourselves.
+    fn process_name(pid: u32) -> Option<String> {
+        unsafe {
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+            let mut path = [0u16; 1024];
+            let mut len = path.len() as u32;
+            let result = QueryFullProcessImageNameW(
+                process,
+                PROCESS_NAME_WIN32,
+                PWSTR(path.as_mut_ptr()),
+                &mut len,
+            );
+            let _ = CloseHandle(process);
+            result.ok()?;
+            let path = String::from_utf16_lossy(&path[..len as usize]);
+            path.rsplit('\\').next().map(str::to_owned)
+        }
+    }
+
+    /// The bounds DWM draws the window at, falling back to the window rect
+    /// (which includes the invisible resize borders).
+    fn window_rect(window_handle: HWND) -> Rect {
+        let mut rect = RECT::default();
         unsafe {
-            let mut title = [0u16; 512];
-            GetWindowTextW(window_handle, &mut title);
-            let mut title = String::from_utf16_lossy(&title);
-            truncate_to_first_null_char(&mut title);
-
-            let mut class_name = [0u16; 512];
-            GetClassNameW(window_handle, &mut class_name);
-            let mut class_name = String::from_utf16_lossy(&class_name);
-            truncate_to_first_null_char(&mut class_name);
-
-            Self {
-                handle: window_handle,
-                title,
-                class_name,
+            if DwmGetWindowAttribute(
+                window_handle,
+                DWMWA_EXTENDED_FRAME_BOUNDS,
+                &mut rect as *mut _ as *mut _,
+                std::mem::size_of::<RECT>() as u32,
+            )
+            .is_err()
+            {
+                let _ = GetWindowRect(window_handle, &mut rect);
             }
         }
+        Rect::new(
+            rect.left,
+            rect.top,
+            (rect.right - rect.left).max(0) as u32,
+            (rect.bottom - rect.top).max(0) as u32,
+        )
     }
 
+    fn truncate_to_first_null_char(input: &mut String) {
+        if let Some(index) = input.find('\0') {
+            input.truncate(index);
+        }
+    }
+}
+
+impl WindowInfo {
     pub fn matches_title_and_class_name(&self, title: &str, class_name: &str) -> bool {
         self.title == title && self.class_name == class_name
     }
+
+    /// The 1-based id of the monitor showing the most of the window.
+    pub fn monitor_id(&self, displays: &[DisplayInfo]) -> Option<usize> {
+        displays
+            .iter()
+            .enumerate()
+            .filter_map(|(index, display)| {
+                let overlap = display.rect.intersection(&self.rect)?;
+                Some((overlap.width as u64 * overlap.height as u64, index + 1))
+            })
+            .max_by_key(|(area, index)| (*area, std::cmp::Reverse(*index)))
+            .map(|(_, id)| id)
+    }
+}
+
+/// Narrows down windows by their owner, class or handle. Fields left as `None`
+/// match every window.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WindowFilter {
+    pub pid: Option<u32>,
+    /// The executable's file name, e.g. `notepad.exe`. The extension can be
+    /// left out for `.exe` files.
+    pub process_name: Option<String>,
+    pub class_name: Option<String>,
+    /// The raw `HWND` of the window.
+    pub handle: Option<isize>,
+}
+
+impl WindowFilter {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Names and class names are compared ignoring case, like Windows does.
+    pub fn matches(&self, window: &WindowInfo) -> bool {
+        let process_matches = |name: &str| match &window.process_name {
+            Some(process_name) => {
+                let process_name = process_name.to_lowercase();
+                let name = name.to_lowercase();
+                process_name == name || process_name == format!("{}.exe", name)
+            }
+            None => false,
+        };
+        self.pid.is_none_or(|pid| window.pid == pid)
+            && self.process_name.as_deref().is_none_or(process_matches)
+            && self.class_name.as_deref().is_none_or(|class_name| {
+                window.class_name.to_lowercase() == class_name.to_lowercase()
+            })
+            && self.handle.is_none_or(|handle| window.handle == handle)
+    }
+}
+
+impl fmt::Display for WindowFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(pid) = self.pid {
+            parts.push(format!("pid {}", pid));
+        }
+        if let Some(process_name) = &self.process_name {
+            parts.push(format!("process {}", process_name));
+        }
+        if let Some(class_name) = &self.class_name {
+            parts.push(format!("class {}", class_name));
+        }
+        if let Some(handle) = self.handle {
+            parts.push(format!("hwnd {:#x}", handle));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Returns the windows whose title contains `query`, ignoring case.
+pub fn find_windows(windows: &[WindowInfo], query: &str) -> Vec<WindowInfo> {
+    TitleMatcher::new(query, MatchMode::Substring, false)
+        .expect("escaped queries are valid patterns")
+        .filter(windows)
+}
+
+/// How to pick one of several windows matching a query without asking.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WindowSelection {
+    First,
+    Last,
+    /// The window covering the most pixels.
+    Largest,
+    /// The window the user is working in.
+    Foreground,
+    /// A 0-based index into the matches, as listed by the selection prompt.
+    Index(usize),
+}
+
+impl WindowSelection {
+    /// Picks the index of a window from `windows`. `foreground` is the handle of
+    /// the foreground window, only needed for [`WindowSelection::Foreground`].
+    pub fn select(&self, windows: &[WindowInfo], foreground: Option<isize>) -> Option<usize> {
+        match self {
+            WindowSelection::First => (!windows.is_empty()).then_some(0),
+            WindowSelection::Last => windows.len().checked_sub(1),
+            WindowSelection::Largest => windows
+                .iter()
+                .enumerate()
+                .max_by_key(|(index, window)| {
+                    let area = window.rect.width as u64 * window.rect.height as u64;
+                    (area, std::cmp::Reverse(*index))
+                })
+                .map(|(index, _)| index),
+            WindowSelection::Foreground => {
+                let foreground = foreground?;
+                windows
+                    .iter()
+                    .position(|window| window.handle == foreground)
+            }
+            WindowSelection::Index(index) => (*index < windows.len()).then_some(*index),
+        }
+    }
 }
 
-fn truncate_to_first_null_char(input: &mut String) {
-    if let Some(index) = input.find('\0') {
-        input.truncate(index);
+impl fmt::Display for WindowSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WindowSelection::First => write!(f, "first"),
+            WindowSelection::Last => write!(f, "last"),
+            WindowSelection::Largest => write!(f, "largest"),
+            WindowSelection::Foreground => write!(f, "foreground"),
+            WindowSelection::Index(index) => write!(f, "index:{}", index),
+        }
+    }
+}
+
+impl FromStr for WindowSelection {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        let lowercase = input.to_lowercase();
+        match lowercase.as_str() {
+            "first" => Ok(WindowSelection::First),
+            "last" => Ok(WindowSelection::Last),
+            "largest" => Ok(WindowSelection::Largest),
+            "foreground" => Ok(WindowSelection::Foreground),
+            _ => match lowercase.strip_prefix("index:").map(str::parse) {
+                Some(Ok(index)) => Ok(WindowSelection::Index(index)),
+                _ => Err(format!(
+                    "'{}' isn't a window selection, expecting first, last, largest, foreground or index:N",
+                    input
+                )),
+            },
+        }
     }
 }